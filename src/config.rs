@@ -6,41 +6,173 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::{error::Error, fs};
-use std::collections::HashMap;
+mod sample;
+
+use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use lettre::SmtpTransport;
+use lettre::message::Mailbox;
 
-use json;
 use json::JsonValue;
 
 use crate::json_helper::*;
+use crate::http_client::load_certificate;
+use crate::error::AppError;
+use crate::notification::{MessageFormat, Severity};
+use crate::i18n::Language;
+
+pub use sample::sample_config;
 
 #[derive(Debug)]
 pub struct Config {
-    pub admin_notifications: Vec<String>,
+    pub admin_notifications: AdminNotificationSettings,
+    /// Title of the admin messages, tells apart several pollers sharing a channel
+    pub admin_title: String,
     pub services: Vec<ServiceSettings>,
-    pub notifications: HashMap<String, NotificationSettings>
+    pub notifications: HashMap<String, NotificationSettings>,
+    pub shutdown_timeout_secs: u32,
+    /// Timeout of every HTTP request, applied to the shared HTTP clients
+    pub http_timeout_secs: u32,
+    pub max_runtime_secs: Option<u32>,
+    pub log_file: Option<LogFileSettings>,
+    /// Test every service and notificator once at startup
+    pub self_check: bool,
+    /// Port of the HTTP API with the `/healthz` endpoint
+    pub health_port: Option<u16>,
+    /// Address the HTTP API listens on, only local clients by default
+    pub health_bind_address: IpAddr,
+    /// Serve `POST /poll/{title}` on the HTTP API
+    pub poll_trigger: bool,
+    /// Interval of the "still running" admin message with the last poll of each service
+    pub heartbeat_secs: Option<u32>,
+    /// Requested while all services work, for an external dead man's switch like healthchecks.io
+    pub ping_url: Option<String>,
+    pub ping_interval_secs: u32,
+    /// JSON Lines file every poll, change and notification is appended to
+    pub events_file: Option<String>,
+    pub restart_debounce: Option<RestartDebounceSettings>,
+    /// Poll all services from one thread, taking turns, instead of a thread per service
+    pub single_threaded: bool
 }
 
 impl Config {
-    pub fn read_from_file(filename: &str) -> Result<Config, Box<dyn Error>> {
-        let json_str = fs::read_to_string(filename)?;
-        let config = Config::read_from_json_str(&json_str)?;
+    pub fn read_from_file(filename: &str) -> Result<Config, AppError> {
+        let json_obj = json::parse(&fs::read_to_string(filename)?)?;
+        Config::load_from_json_object(&json_obj)
+    }
+
+    /// Reads a config file like [`Config::read_from_file`], but leaves the references
+    /// between the sections unchecked, so all problems can be listed by [`Config::check`].
+    pub fn read_unvalidated_from_file(filename: &str) -> Result<Config, AppError> {
+        let json_obj = json::parse(&fs::read_to_string(filename)?)?;
+        Config::load_unvalidated(&json_obj)
+    }
+
+    /// Reads every `*.json` file of `dir` in alphabetical order and merges them into one config.
+    ///
+    /// Each file may contribute services and notifications, and may reference notifications
+    /// defined in another file. Global settings like `shutdown_timeout_secs` are taken from
+    /// the first file.
+    pub fn read_from_dir(dir: &str) -> Result<Config, AppError> {
+        let config = Config::read_unvalidated_from_dir(dir)?;
+        config.validate()?;
         Ok(config)
     }
 
-    fn read_from_json_str(str: &String) -> Result<Config, Box<dyn Error>> {
-        let json_obj = json::parse(str)?;
-        let config = Config::load_from_json_object(&json_obj)?;
+    /// Reads a config directory like [`Config::read_from_dir`], but leaves the
+    /// references between the sections unchecked.
+    pub fn read_unvalidated_from_dir(dir: &str) -> Result<Config, AppError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut merged: Option<Config> = None;
+        for path in paths {
+            let filename = path.to_string_lossy().into_owned();
+            let config = Config::read_section_file(&filename).map_err(|error| in_file(&filename, error))?;
+            match merged.as_mut() {
+                Some(merged) => merged.merge(config).map_err(|error| in_file(&filename, error))?,
+                None => merged = Some(config)
+            }
+        }
+        let config = match merged {
+            Some(config) => config,
+            None => return Err(AppError::Config(format!("No *.json files found in {}", dir)))
+        };
+        // Missing sections would silently result in a poller doing nothing
+        if config.services.is_empty() && config.notifications.is_empty() {
+            return Err(AppError::Config(format!("No services or notifications are defined in {}", dir)));
+        }
         Ok(config)
     }
 
-    fn load_from_json_object(obj: &JsonValue) -> Result<Config, Box<dyn Error>> {
+    /// Adds the services, notifications and admin notifications of `other`.
+    ///
+    /// The global settings of `self` are kept. References are not checked, as they may be
+    /// resolved by a config merged later on. Call `validate` on the final config.
+    pub fn merge(&mut self, other: Config) -> Result<(), AppError> {
+        for (name, settings) in other.notifications {
+            if self.notifications.contains_key(&name) {
+                return Err(AppError::Config(format!("Notification {} is defined more than once", name)));
+            }
+            self.notifications.insert(name, settings);
+        }
+        self.services.extend(other.services);
+        for name in other.admin_notifications.errors {
+            if !self.admin_notifications.errors.contains(&name) {
+                self.admin_notifications.errors.push(name);
+            }
+        }
+        for name in other.admin_notifications.lifecycle {
+            if !self.admin_notifications.lifecycle.contains(&name) {
+                self.admin_notifications.lifecycle.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a file of a config directory, which may leave out any section.
+    fn read_section_file(filename: &str) -> Result<Config, AppError> {
+        let json_obj = json::parse(&fs::read_to_string(filename)?)?;
+        if !json_obj["services"].is_null() {
+            field(&json_obj, "services", expect_array)?;
+        }
+        if !json_obj["notifications"].is_null() {
+            field(&json_obj, "notifications", expect_object)?;
+        }
+        Config::load_sections(&json_obj)
+    }
+
+    fn load_from_json_object(obj: &JsonValue) -> Result<Config, AppError> {
+        let config = Config::load_unvalidated(obj)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn load_unvalidated(obj: &JsonValue) -> Result<Config, AppError> {
+        // Missing sections would silently result in a poller doing nothing
+        field(obj, "services", expect_array)?;
+        field(obj, "notifications", expect_object)?;
+        Config::load_sections(obj)
+    }
+
+    fn load_sections(obj: &JsonValue) -> Result<Config, AppError> {
         let config = Config{
-            admin_notifications: to_str_array(&obj["admin_notifications"])?,
+            admin_notifications: field(obj, "admin_notifications", AdminNotificationSettings::load_from_json_object)?,
+            admin_title: field(obj, "admin_title", obj_to_opt_str)?.unwrap_or_else(|| String::from("COVID Vaccination Poll - Admin")),
             services: {
+                let default_sleep = field(obj, "default_sleep", obj_to_opt_u32)?;
                 let mut srv: Vec<ServiceSettings> = Vec::new();
-                for content in obj["services"].members() {
-                    let settings = ServiceSettings::load_from_json_object(&content)?;
+                for (index, content) in obj["services"].members().enumerate() {
+                    let settings = in_path(&format!("services[{}]", index), ServiceSettings::load_from_json_object(content, default_sleep))?;
                     srv.push(settings);
                 }
                 srv
@@ -48,79 +180,773 @@ impl Config {
             notifications: {
                 let mut notifs: HashMap<String, NotificationSettings> = HashMap::new();
                 for (key, content) in obj["notifications"].entries() {
-                    let settings = NotificationSettings::load_from_json_object(&content)?;
+                    let settings = in_path(&format!("notifications.{}", key), NotificationSettings::load_from_json_object(content))?;
                     notifs.insert(String::from(key), settings);
                 }
                 notifs
-            }
+            },
+            shutdown_timeout_secs: field(obj, "shutdown_timeout_secs", |v| obj_to_u32_or(v, 30))?,
+            http_timeout_secs: field(obj, "http_timeout_secs", |v| match obj_to_u32_or(v, 60)? {
+                0 => Err(AppError::Config(String::from("must be at least 1"))),
+                secs => Ok(secs)
+            })?,
+            max_runtime_secs: field(obj, "max_runtime_secs", obj_to_opt_u32)?,
+            log_file: if obj["log_file"].is_null() {
+                None
+            } else {
+                Some(field(obj, "log_file", LogFileSettings::load_from_json_object)?)
+            },
+            self_check: field(obj, "self_check", |v| obj_to_bool_or(v, false))?,
+            health_port: if obj["health_port"].is_null() {
+                None
+            } else {
+                Some(field(obj, "health_port", obj_to_u16)?)
+            },
+            health_bind_address: match field(obj, "health_bind_address", obj_to_opt_str)? {
+                Some(address) => match address.parse::<IpAddr>() {
+                    Ok(address) => address,
+                    Err(_) => return Err(field_error("health_bind_address", String::from("not a valid IP address")))
+                },
+                None => IpAddr::V4(Ipv4Addr::LOCALHOST)
+            },
+            poll_trigger: field(obj, "poll_trigger", |v| obj_to_bool_or(v, false))?,
+            heartbeat_secs: match field(obj, "heartbeat_secs", obj_to_opt_u32)? {
+                Some(0) => return Err(field_error("heartbeat_secs", String::from("must not be 0"))),
+                secs => secs
+            },
+            ping_url: field(obj, "ping_url", obj_to_opt_str)?,
+            ping_interval_secs: field(obj, "ping_interval_secs", |v| match obj_to_u32_or(v, 60)? {
+                0 => Err(AppError::Config(String::from("must be at least 1"))),
+                secs => Ok(secs)
+            })?,
+            events_file: field(obj, "events_file", obj_to_opt_str)?,
+            restart_debounce: if obj["restart_debounce"].is_null() {
+                None
+            } else {
+                Some(field(obj, "restart_debounce", RestartDebounceSettings::load_from_json_object)?)
+            },
+            single_threaded: field(obj, "single_threaded", |v| obj_to_bool_or(v, false))?
         };
         Ok(config)
     }
+
+    /// Describes settings which are valid but probably not intended.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.services.iter().any(|srv| srv.enabled) {
+            warnings.push(String::from("No enabled services are configured. Nothing will be polled."));
+        }
+        for srv in self.services.iter().filter(|srv| srv.enabled && srv.cron.is_none()) {
+            let sleep = match &srv.adaptive {
+                Some(adaptive) => adaptive.min_sleep,
+                None => srv.sleep
+            };
+            if sleep < MIN_RECOMMENDED_SLEEP_SECS {
+                warnings.push(format!("Service {} polls every {} s. Intervals below {} s may get you blocked.",
+                                      srv.title, sleep, MIN_RECOMMENDED_SLEEP_SECS));
+            }
+        }
+        if self.notifications.is_empty() {
+            warnings.push(String::from("No notifications are configured. Free appointments will only be logged."));
+        }
+        let admin = &self.admin_notifications;
+        if admin.errors.is_empty() && admin.lifecycle.is_empty() {
+            warnings.push(String::from("No admin_notifications are configured. Errors will only be logged."));
+        }
+        warnings
+    }
+
+    /// Checks the references between the sections, failing with the first problem.
+    ///
+    /// Services are identified by their title, so titles must be unique.
+    pub fn validate(&self) -> Result<(), AppError> {
+        match self.check().errors().into_iter().next() {
+            Some(error) => Err(AppError::Config(error)),
+            None => Ok(())
+        }
+    }
+
+    /// Lists all problems with the references between the sections.
+    pub fn check(&self) -> Validation {
+        let mut validation = Validation::default();
+        let mut titles = HashSet::new();
+        for srv in self.services.iter() {
+            if !titles.insert(srv.title.as_str()) && !validation.duplicate_titles.contains(&srv.title) {
+                validation.duplicate_titles.push(srv.title.clone());
+            }
+        }
+        let admin = &self.admin_notifications;
+        let references = admin.errors.iter().map(|name| (name, String::from("admin_notifications.errors")))
+            .chain(admin.lifecycle.iter().map(|name| (name, String::from("admin_notifications.lifecycle"))))
+            .chain(self.services.iter().flat_map(|srv| srv.notifications.iter().map(move |name| (name, format!("services.{}", srv.title)))));
+        for (name, referenced_by) in references {
+            if !self.notifications.contains_key(name) {
+                validation.dangling_references.push(DanglingReference{notification: name.clone(), referenced_by});
+            }
+        }
+        validation
+    }
+}
+
+/// Problems with the references between the sections of a config, see [`Config::check`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validation {
+    /// Titles used by more than one service
+    pub duplicate_titles: Vec<String>,
+    pub dangling_references: Vec<DanglingReference>
+}
+
+/// A notification which is referenced but not defined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DanglingReference {
+    pub notification: String,
+    /// Where the notification is referenced, e.g. `services.Impfzentrum`
+    pub referenced_by: String
+}
+
+impl Validation {
+    pub fn is_ok(&self) -> bool {
+        self.duplicate_titles.is_empty() && self.dangling_references.is_empty()
+    }
+
+    /// Describes every problem in one sentence, a notification referenced in several places once.
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors: Vec<String> = self.duplicate_titles.iter()
+            .map(|title| format!("services[].title {} is not unique", title))
+            .collect();
+        for reference in self.dangling_references.iter() {
+            let error = format!("Notification {} is referenced but not defined", reference.notification);
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+        errors
+    }
+}
+
+/// Prefixes the errors of a file in a config directory with its name.
+fn in_file(filename: &str, error: AppError) -> AppError {
+    match error {
+        AppError::ConfigField{path, msg} => AppError::ConfigField{path: format!("{}: {}", filename, path), msg},
+        other => AppError::ConfigField{path: String::from(filename), msg: match other {
+            AppError::Config(msg) => msg,
+            other => other.to_string()
+        }}
+    }
+}
+
+/// Notificators for the admin messages, separated by kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminNotificationSettings {
+    /// Failed polls and notifications
+    pub errors: Vec<String>,
+    /// Start, stop and reload of the app
+    pub lifecycle: Vec<String>
+}
+
+impl AdminNotificationSettings {
+    /// Accepts a plain array, which routes all admin messages to the same notificators.
+    fn load_from_json_object(obj: &JsonValue) -> Result<AdminNotificationSettings, AppError> {
+        if obj.is_array() {
+            let names = to_str_array(obj)?;
+            return Ok(AdminNotificationSettings{
+                errors: names.clone(),
+                lifecycle: names
+            });
+        }
+        let optional_array = |obj: &JsonValue| if obj.is_null() {
+            Ok(Vec::new())
+        } else {
+            to_str_array(obj)
+        };
+        let settings = AdminNotificationSettings{
+            errors: field(obj, "errors", optional_array)?,
+            lifecycle: field(obj, "lifecycle", optional_array)?
+        };
+        Ok(settings)
+    }
 }
 
 #[derive(Debug)]
+pub struct LogFileSettings {
+    pub path: String,
+    pub max_size_bytes: u32,
+    pub keep: u32
+}
+
+impl LogFileSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<LogFileSettings, AppError> {
+        let settings = LogFileSettings{
+            path: field(obj, "path", obj_to_str)?,
+            max_size_bytes: field(obj, "max_size_bytes", |v| obj_to_u32_or(v, 10 * 1024 * 1024))?,
+            keep: field(obj, "keep", |v| obj_to_u32_or(v, 5))?
+        };
+        Ok(settings)
+    }
+}
+
+/// Suppresses the start and stop messages of runs shorter than `min_run_secs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestartDebounceSettings {
+    /// Keeps the start time of the last run across restarts
+    pub state_file: String,
+    pub min_run_secs: u32
+}
+
+impl RestartDebounceSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<RestartDebounceSettings, AppError> {
+        let settings = RestartDebounceSettings{
+            state_file: field(obj, "state_file", obj_to_str)?,
+            min_run_secs: field(obj, "min_run_secs", |v| obj_to_u32_or(v, 300))?
+        };
+        Ok(settings)
+    }
+}
+
+// Loaded once per service, so the size of the variants does not matter
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
 pub enum ServiceProviderSettings {
-    Booked4us(Booked4usSettings)
+    Booked4us(Booked4usSettings),
+    Doctolib(DoctolibSettings)
 }
 
-#[derive(Debug)]
+impl ServiceProviderSettings {
+    /// Name of the provider as written in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServiceProviderSettings::Booked4us(_) => "booked4us",
+            ServiceProviderSettings::Doctolib(_) => "doctolib"
+        }
+    }
+
+    /// Servers which are polled.
+    pub fn urls(&self) -> &[String] {
+        match self {
+            ServiceProviderSettings::Booked4us(settings) => &settings.urls,
+            ServiceProviderSettings::Doctolib(settings) => std::slice::from_ref(&settings.url)
+        }
+    }
+}
+
+/// Intervals below this are likely to get the client blocked by the polled server.
+const MIN_RECOMMENDED_SLEEP_SECS: u32 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ServiceSettings {
     pub provider: ServiceProviderSettings,
     pub notifications: Vec<String>,
     pub sleep: u32,
-    pub title: String
+    pub jitter_secs: u32,
+    /// Delays the first poll, so services with the same sleep do not poll at the same time
+    pub start_offset_secs: u32,
+    pub cron: Option<cron::Schedule>,
+    pub adaptive: Option<AdaptiveSettings>,
+    pub title: String,
+    /// Attached to the notifications, e.g. the region, for filtering on the receiving side
+    pub tags: Vec<String>,
+    /// Pause polling after this many failed polls in a row
+    pub max_consecutive_failures: Option<u32>,
+    /// Pause after `max_consecutive_failures` before trying again
+    pub circuit_reset_secs: u32,
+    /// Stop at the first failed notification instead of trying the remaining ones
+    pub notify_fail_fast: bool,
+    /// Disabled services are kept in the config but not polled
+    pub enabled: bool
 }
 
 impl ServiceSettings {
-    fn load_from_json_object(obj: &JsonValue) -> Result<ServiceSettings, Box<dyn Error>> {
-        let provider = obj_to_str(&obj["provider"])?;
+    /// `default_sleep` is used if the service does not set its own `sleep`.
+    fn load_from_json_object(obj: &JsonValue, default_sleep: Option<u32>) -> Result<ServiceSettings, AppError> {
+        let provider = field(obj, "provider", obj_to_str)?;
         let srv: ServiceProviderSettings = match provider.as_str() {
-            "booked4us" => ServiceProviderSettings::Booked4us(Booked4usSettings::load_from_json_object(&obj["settings"])?),
-            _ => return Err(ParseError::new("services[].provider is invalid"))
+            "booked4us" => ServiceProviderSettings::Booked4us(field(obj, "settings", Booked4usSettings::load_from_json_object)?),
+            "doctolib" => ServiceProviderSettings::Doctolib(field(obj, "settings", DoctolibSettings::load_from_json_object)?),
+            _ => return Err(field_error("provider", format!("unknown provider \"{}\"", provider)))
+        };
+        let notifications = field(obj, "notifications", to_str_array)?;
+        let cron = if obj["cron"].is_null() {
+            None
+        } else {
+            match cron::Schedule::from_str(field(obj, "cron", obj_to_str)?.as_str()) {
+                Ok(schedule) => Some(schedule),
+                Err(error) => return Err(field_error("cron", format!("invalid cron expression: {}", error)))
+            }
         };
-        let notifications = to_str_array(&obj["notifications"])?;
+        // A cron schedule replaces the fixed interval
+        let sleep = if cron.is_some() {
+            field(obj, "sleep", |v| obj_to_u32_or(v, 0))?
+        } else {
+            let sleep = match (field(obj, "sleep", obj_to_opt_u32)?, default_sleep) {
+                (Some(sleep), _) | (None, Some(sleep)) => sleep,
+                (None, None) => return Err(field_error("sleep", String::from("missing and no default_sleep is set")))
+            };
+            if sleep == 0 {
+                return Err(field_error("sleep", String::from("must not be 0")));
+            }
+            sleep
+        };
+        let adaptive = if obj["adaptive"].is_null() {
+            None
+        } else if cron.is_some() {
+            return Err(field_error("adaptive", String::from("cannot be combined with cron")));
+        } else {
+            Some(field(obj, "adaptive", AdaptiveSettings::load_from_json_object)?)
+        };
+        let jitter_secs = field(obj, "jitter_secs", |v| obj_to_u32_or(v, 0))?;
+        // Otherwise two polls could run back to back
+        match (&cron, &adaptive) {
+            (Some(_), _) => (),
+            (None, Some(adaptive)) if jitter_secs >= adaptive.min_sleep => return Err(field_error("jitter_secs", String::from("must be less than min_sleep"))),
+            (None, None) if jitter_secs >= sleep => return Err(field_error("jitter_secs", String::from("must be less than sleep"))),
+            _ => ()
+        }
         Ok(ServiceSettings{
             provider: srv,
             notifications,
-            sleep: obj_to_u32(&obj["sleep"])?,
-            title: obj_to_str(&obj["title"])?
+            sleep,
+            cron,
+            adaptive,
+            jitter_secs,
+            start_offset_secs: field(obj, "start_offset_secs", |v| obj_to_u32_or(v, 0))?,
+            title: field(obj, "title", obj_to_str)?,
+            tags: match field(obj, "tags", to_str_array)? {
+                tags if tags.iter().any(|tag| tag.trim().is_empty()) => return Err(field_error("tags", String::from("must not contain empty tags"))),
+                tags => tags
+            },
+            max_consecutive_failures: match field(obj, "max_consecutive_failures", obj_to_opt_u32)? {
+                Some(0) => return Err(field_error("max_consecutive_failures", String::from("must not be 0"))),
+                max => max
+            },
+            circuit_reset_secs: field(obj, "circuit_reset_secs", |v| obj_to_u32_or(v, 3600))?,
+            notify_fail_fast: field(obj, "notify_fail_fast", |v| obj_to_bool_or(v, false))?,
+            enabled: field(obj, "enabled", |v| obj_to_bool_or(v, true))?
         })
     }
 }
 
-#[derive(Debug)]
+/// Bounds of an adaptive poll interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveSettings {
+    pub min_sleep: u32,
+    pub max_sleep: u32,
+    /// Growth of the interval after each poll without new appointments
+    pub factor: f64
+}
+
+impl AdaptiveSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<AdaptiveSettings, AppError> {
+        let settings = AdaptiveSettings{
+            min_sleep: field(obj, "min_sleep", obj_to_u32)?,
+            max_sleep: field(obj, "max_sleep", obj_to_u32)?,
+            factor: field(obj, "factor", |v| obj_to_f64_or(v, 1.5))?
+        };
+        if settings.min_sleep == 0 {
+            return Err(field_error("min_sleep", String::from("must not be 0")));
+        }
+        if settings.max_sleep < settings.min_sleep {
+            return Err(field_error("max_sleep", String::from("must not be less than min_sleep")));
+        }
+        if settings.factor < 1.0 {
+            return Err(field_error("factor", String::from("must be at least 1")));
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Booked4usSettings {
-    pub url: String
+    /// Servers whose calendars are merged, `url` may be a single URL or a list
+    pub urls: Vec<String>,
+    /// Also set by the `socks5` and `tor` shortcuts
+    pub proxy: Option<String>,
+    pub headers: HeaderMap,
+    pub user_agent: Option<String>,
+    pub auth: Option<HttpAuth>,
+    /// Session established before polling, for servers which require a login
+    pub login: Option<LoginSettings>,
+    pub message_mode: MessageMode,
+    pub message_template: Option<String>,
+    /// Language of the built-in messages, templates are used as they are
+    pub language: Language,
+    /// Link of each listed calendar, with the placeholders `{url}` and `{id}`
+    pub booking_url_template: Option<String>,
+    /// Number of times a response which is no valid JSON is fetched again
+    pub parse_retries: u32,
+    /// Fewer newly freed calendars only produce a normal message
+    pub min_added: u32,
+    /// Send an urgent message when a calendar is no longer free, e.g. a missed one
+    pub notify_on_removed: bool,
+    /// Only these calendars are polled, all if `None`
+    pub watch_ids: Option<HashSet<u64>>,
+    /// Newly freed calendars with these IDs always cause an urgent message with the highest priority
+    pub important_ids: HashSet<u64>,
+    /// Calendars freed again within this time after an urgent message only produce a normal one
+    pub urgent_cooldown_secs: u32,
+    /// Pause between the free slot requests of the calendars, against rate limits
+    pub inter_request_delay_ms: u32,
+    /// The first poll only records the free calendars without a message
+    pub silent_first_poll: bool,
+    pub api_base_path: String,
+    pub tls: TlsSettings,
+    pub network: NetworkSettings
 }
 
 impl Booked4usSettings {
-    fn load_from_json_object(obj: &JsonValue) -> Result<Booked4usSettings, Box<dyn Error>> {
+    pub(crate) fn load_from_json_object(obj: &JsonValue) -> Result<Booked4usSettings, AppError> {
         let settings = Booked4usSettings{
-            url: obj_to_str(&obj["url"])?
+            urls: field(obj, "url", load_urls)?,
+            proxy: match (field(obj, "proxy", load_proxy)?, load_socks5_proxy(obj)?) {
+                (Some(_), Some(_)) => return Err(field_error("proxy", String::from("must not be combined with socks5 or tor"))),
+                (proxy, socks5) => proxy.or(socks5)
+            },
+            headers: field(obj, "headers", load_headers)?,
+            user_agent: field(obj, "user_agent", obj_to_opt_str)?,
+            auth: if obj["auth"].is_null() {
+                None
+            } else {
+                Some(field(obj, "auth", HttpAuth::load_from_json_object)?)
+            },
+            login: if obj["login"].is_null() {
+                None
+            } else {
+                Some(field(obj, "login", LoginSettings::load_from_json_object)?)
+            },
+            message_mode: match field(obj, "message_mode", obj_to_opt_str)?.as_deref() {
+                None | Some("full") => MessageMode::Full,
+                Some("added_only") => MessageMode::AddedOnly,
+                Some("summary") => MessageMode::Summary,
+                Some(mode) => return Err(field_error("message_mode", format!("unknown mode \"{}\", expected full, added_only or summary", mode)))
+            },
+            message_template: field(obj, "message_template", obj_to_opt_str)?,
+            language: field(obj, "language", load_language)?,
+            booking_url_template: field(obj, "booking_url_template", obj_to_opt_str)?,
+            parse_retries: field(obj, "parse_retries", |v| obj_to_u32_or(v, 2))?,
+            min_added: field(obj, "min_added", |v| obj_to_u32_or(v, 1))?,
+            notify_on_removed: field(obj, "notify_on_removed", |v| obj_to_bool_or(v, false))?,
+            watch_ids: if obj["watch_ids"].is_null() {
+                None
+            } else {
+                match field(obj, "watch_ids", to_id_array)? {
+                    ids if ids.is_empty() => return Err(field_error("watch_ids", String::from("must not be empty, omit it to watch all calendars"))),
+                    ids => Some(ids.into_iter().collect())
+                }
+            },
+            important_ids: field(obj, "important_ids", to_id_array)?.into_iter().collect(),
+            urgent_cooldown_secs: field(obj, "urgent_cooldown_secs", |v| obj_to_u32_or(v, 0))?,
+            inter_request_delay_ms: field(obj, "inter_request_delay_ms", |v| obj_to_u32_or(v, 0))?,
+            silent_first_poll: field(obj, "silent_first_poll", |v| obj_to_bool_or(v, false))?,
+            api_base_path: match field(obj, "api_base_path", obj_to_opt_str)? {
+                Some(path) => String::from(path.trim_end_matches('/')),
+                None => String::from("/rest-v2/api")
+            },
+            tls: TlsSettings::load_from_json_object(obj)?,
+            network: NetworkSettings::load_from_json_object(obj)?
         };
+        if let Some(user_agent) = &settings.user_agent {
+            if HeaderValue::from_str(user_agent).is_err() {
+                return Err(field_error("user_agent", String::from("not a valid header value")));
+            }
+        }
         Ok(settings)
     }
 }
 
-#[derive(Debug)]
-pub enum NotificationSettings {
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoctolibSettings {
+    /// Server of the `availabilities.json` API
+    pub url: String,
+    /// Page of the practice where the appointments are booked
+    pub booking_url: String,
+    /// Kinds of appointment, e.g. the first vaccination
+    pub visit_motive_ids: Vec<u64>,
+    pub agenda_ids: Vec<u64>,
+    pub practice_ids: Vec<u64>,
+    /// First day which is polled, today if `None`
+    pub start_date: Option<chrono::NaiveDate>,
+    /// Number of days which are polled
+    pub days: u32,
+    pub language: Language,
+    pub user_agent: Option<String>,
+    pub proxy: Option<String>,
+    pub tls: TlsSettings,
+    pub network: NetworkSettings
+}
+
+impl DoctolibSettings {
+    pub(crate) fn load_from_json_object(obj: &JsonValue) -> Result<DoctolibSettings, AppError> {
+        let settings = DoctolibSettings{
+            url: match field(obj, "url", obj_to_opt_str)? {
+                Some(url) => String::from(url.trim_end_matches('/')),
+                None => String::from("https://www.doctolib.de")
+            },
+            booking_url: field(obj, "booking_url", obj_to_str)?,
+            visit_motive_ids: field(obj, "visit_motive_ids", to_id_array)?,
+            agenda_ids: field(obj, "agenda_ids", to_id_array)?,
+            practice_ids: field(obj, "practice_ids", to_id_array)?,
+            start_date: match field(obj, "start_date", obj_to_opt_str)? {
+                Some(date) => match chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+                    Ok(date) => Some(date),
+                    Err(_) => return Err(field_error("start_date", String::from("expected a date like 2021-05-01")))
+                },
+                None => None
+            },
+            days: field(obj, "days", |v| obj_to_u32_or(v, 7))?,
+            language: field(obj, "language", load_language)?,
+            user_agent: field(obj, "user_agent", obj_to_opt_str)?,
+            proxy: match (field(obj, "proxy", load_proxy)?, load_socks5_proxy(obj)?) {
+                (Some(_), Some(_)) => return Err(field_error("proxy", String::from("must not be combined with socks5 or tor"))),
+                (proxy, socks5) => proxy.or(socks5)
+            },
+            tls: TlsSettings::load_from_json_object(obj)?,
+            network: NetworkSettings::load_from_json_object(obj)?
+        };
+        if settings.visit_motive_ids.is_empty() {
+            return Err(field_error("visit_motive_ids", String::from("must not be empty")));
+        }
+        if settings.agenda_ids.is_empty() {
+            return Err(field_error("agenda_ids", String::from("must not be empty")));
+        }
+        if settings.days == 0 {
+            return Err(field_error("days", String::from("must not be 0")));
+        }
+        if let Some(user_agent) = &settings.user_agent {
+            if HeaderValue::from_str(user_agent).is_err() {
+                return Err(field_error("user_agent", String::from("not a valid header value")));
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Certificate verification of HTTPS connections to self-hosted servers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TlsSettings {
+    /// Accept any certificate, e.g. a self-signed one
+    pub insecure: bool,
+    /// Additional trusted CA certificate in PEM format
+    pub ca_cert_path: Option<String>
+}
+
+impl TlsSettings {
+    /// Reads `tls_insecure` and `ca_cert_path` from the settings of a provider.
+    fn load_from_json_object(obj: &JsonValue) -> Result<TlsSettings, AppError> {
+        let settings = TlsSettings{
+            insecure: field(obj, "tls_insecure", |v| obj_to_bool_or(v, false))?,
+            ca_cert_path: field(obj, "ca_cert_path", obj_to_opt_str)?
+        };
+        if let Some(path) = &settings.ca_cert_path {
+            if let Err(error) = load_certificate(path) {
+                return Err(field_error("ca_cert_path", format!("could not load certificate: {}", error)));
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Connection settings for networks with limited IPv4 or IPv6 connectivity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct NetworkSettings {
+    /// Outgoing connections are bound to this address, which also restricts
+    /// them to its IP version
+    pub local_address: Option<IpAddr>
+}
+
+impl NetworkSettings {
+    /// Reads `ip_version` and `local_address` from the settings of a provider.
+    fn load_from_json_object(obj: &JsonValue) -> Result<NetworkSettings, AppError> {
+        let by_version = match field(obj, "ip_version", obj_to_opt_str)?.as_deref() {
+            None | Some("any") => None,
+            Some("ipv4") => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Some("ipv6") => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            Some(version) => return Err(field_error("ip_version", format!("unknown IP version \"{}\", expected any, ipv4 or ipv6", version)))
+        };
+        let by_address = match field(obj, "local_address", obj_to_opt_str)? {
+            Some(address) => match address.parse::<IpAddr>() {
+                Ok(address) => Some(address),
+                Err(_) => return Err(field_error("local_address", String::from("not a valid IP address")))
+            },
+            None => None
+        };
+        let local_address = match (by_version, by_address) {
+            (Some(_), Some(_)) => return Err(field_error("local_address", String::from("must not be combined with ip_version"))),
+            (version, address) => version.or(address)
+        };
+        Ok(NetworkSettings{local_address})
+    }
+}
+
+/// Detail level of the messages sent by Booked4us.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageMode {
+    /// Newly freed, all free and no longer free calendars
+    Full,
+    /// Only the newly freed calendars
+    AddedOnly,
+    /// Only the number of calendars in each category
+    Summary
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpAuth {
+    Bearer { token: String },
+    Basic { user: String, password: String }
+}
+
+impl HttpAuth {
+    fn load_from_json_object(obj: &JsonValue) -> Result<HttpAuth, AppError> {
+        let auth_type = field(obj, "type", obj_to_str)?;
+        let auth = match auth_type.as_str() {
+            "bearer" => HttpAuth::Bearer{
+                token: field(obj, "token", obj_to_str)?
+            },
+            "basic" => HttpAuth::Basic{
+                user: field(obj, "user", obj_to_str)?,
+                password: field(obj, "password", obj_to_str)?
+            },
+            _ => return Err(field_error("type", format!("unknown type \"{}\", expected bearer or basic", auth_type)))
+        };
+        Ok(auth)
+    }
+}
+
+/// Login form whose session cookie is sent with the following requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginSettings {
+    pub login_url: String,
+    pub username: String,
+    pub password: String
+}
+
+impl LoginSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<LoginSettings, AppError> {
+        Ok(LoginSettings{
+            login_url: field(obj, "login_url", obj_to_str)?,
+            username: field(obj, "username", obj_to_str)?,
+            password: field(obj, "password", obj_to_str)?
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationProviderSettings {
     Email(EmailSettings),
-    Gotify(GotifySettings)
+    Gotify(GotifySettings),
+    Slack(SlackSettings),
+    Mqtt(MqttSettings),
+    Twilio(TwilioSettings),
+    File(FileSettings),
+    Apprise(AppriseSettings),
+    Syslog(SyslogSettings),
+    Mastodon(MastodonSettings),
+    Teams(TeamsSettings),
+    Pushbullet(PushbulletSettings),
+    Failover(FailoverSettings),
+    #[cfg(feature = "desktop")]
+    Desktop(DesktopSettings)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotificationSettings {
+    pub provider: NotificationProviderSettings,
+    pub max_retries: u32,
+    pub retry_delay_secs: u32,
+    pub min_severity: Severity,
+    /// Send every message with this severity instead of the event's
+    pub force_severity: Option<Severity>,
+    pub max_per_minute: Option<u32>,
+    /// Put in front of every title, e.g. `[VaccPoll]`
+    pub prefix: String,
+    /// Appended to every message
+    pub suffix: String,
+    /// Longer messages are shortened, in addition to the provider's own limit
+    pub max_length: Option<u32>,
+    /// Collect messages and send them as one summary at the digest times
+    pub digest: Option<DigestSettings>,
+    /// Append QR codes of the booking links to markdown and HTML messages, if the provider shows images
+    pub qr_code: bool,
+    /// Messages to a disabled notificator are dropped
+    pub enabled: bool
 }
 
 impl NotificationSettings {
-    fn load_from_json_object(obj: &JsonValue) -> Result<NotificationSettings, Box<dyn Error>> {
-        let provider = obj_to_str(&obj["provider"])?;
-        let notif: NotificationSettings = match provider.as_str() {
-            "email" => NotificationSettings::Email(EmailSettings::load_from_json_object(&obj["settings"])?),
-            "gotify" => NotificationSettings::Gotify(GotifySettings::load_from_json_object(&obj["settings"])?),
-            _ => return Err(ParseError::new("notifications[].provider is invalid"))
+    fn load_from_json_object(obj: &JsonValue) -> Result<NotificationSettings, AppError> {
+        let provider = field(obj, "provider", obj_to_str)?;
+        let notif: NotificationProviderSettings = match provider.as_str() {
+            "email" => NotificationProviderSettings::Email(field(obj, "settings", EmailSettings::load_from_json_object)?),
+            "gotify" => NotificationProviderSettings::Gotify(field(obj, "settings", GotifySettings::load_from_json_object)?),
+            "slack" => NotificationProviderSettings::Slack(field(obj, "settings", SlackSettings::load_from_json_object)?),
+            "mqtt" => NotificationProviderSettings::Mqtt(field(obj, "settings", MqttSettings::load_from_json_object)?),
+            "twilio" => NotificationProviderSettings::Twilio(field(obj, "settings", TwilioSettings::load_from_json_object)?),
+            "file" => NotificationProviderSettings::File(field(obj, "settings", FileSettings::load_from_json_object)?),
+            "apprise" => NotificationProviderSettings::Apprise(field(obj, "settings", AppriseSettings::load_from_json_object)?),
+            "syslog" => NotificationProviderSettings::Syslog(field(obj, "settings", SyslogSettings::load_from_json_object)?),
+            "mastodon" => NotificationProviderSettings::Mastodon(field(obj, "settings", MastodonSettings::load_from_json_object)?),
+            "teams" => NotificationProviderSettings::Teams(field(obj, "settings", TeamsSettings::load_from_json_object)?),
+            "pushbullet" => NotificationProviderSettings::Pushbullet(field(obj, "settings", PushbulletSettings::load_from_json_object)?),
+            "failover" => NotificationProviderSettings::Failover(field(obj, "settings", FailoverSettings::load_from_json_object)?),
+            #[cfg(feature = "desktop")]
+            "desktop" => NotificationProviderSettings::Desktop(field(obj, "settings", DesktopSettings::load_from_json_object)?),
+            #[cfg(not(feature = "desktop"))]
+            "desktop" => return Err(field_error("provider", String::from("desktop requires the desktop feature"))),
+            _ => return Err(field_error("provider", format!("unknown provider \"{}\"", provider)))
         };
-        Ok(notif)
+        Ok(NotificationSettings{
+            provider: notif,
+            max_retries: field(obj, "max_retries", |v| obj_to_u32_or(v, 3))?,
+            retry_delay_secs: field(obj, "retry_delay_secs", |v| obj_to_u32_or(v, 2))?,
+            min_severity: field(obj, "min_severity", |v| load_severity(v, Severity::Normal))?,
+            force_severity: if obj["force_severity"].is_null() {
+                None
+            } else {
+                Some(field(obj, "force_severity", |v| load_severity(v, Severity::Normal))?)
+            },
+            max_per_minute: match field(obj, "max_per_minute", obj_to_opt_u32)? {
+                Some(0) => return Err(field_error("max_per_minute", String::from("must not be 0"))),
+                max_per_minute => max_per_minute
+            },
+            prefix: field(obj, "prefix", obj_to_opt_str)?.unwrap_or_default(),
+            suffix: field(obj, "suffix", obj_to_opt_str)?.unwrap_or_default(),
+            max_length: match field(obj, "max_length", obj_to_opt_u32)? {
+                Some(0) => return Err(field_error("max_length", String::from("must not be 0"))),
+                max_length => max_length
+            },
+            digest: if obj["digest"].is_null() {
+                None
+            } else {
+                Some(field(obj, "digest", DigestSettings::load_from_json_object)?)
+            },
+            qr_code: match field(obj, "qr_code", |v| obj_to_bool_or(v, false))? {
+                true if !cfg!(feature = "qr") => return Err(field_error("qr_code", String::from("requires the qr feature"))),
+                qr_code => qr_code
+            },
+            enabled: field(obj, "enabled", |v| obj_to_bool_or(v, true))?
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestSettings {
+    /// Times the collected messages are sent at (local time)
+    pub cron: cron::Schedule,
+    /// Also send every message right away
+    pub realtime: bool
+}
+
+impl DigestSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<DigestSettings, AppError> {
+        let cron = match cron::Schedule::from_str(field(obj, "cron", obj_to_str)?.as_str()) {
+            Ok(schedule) => schedule,
+            Err(error) => return Err(field_error("cron", format!("invalid cron expression: {}", error)))
+        };
+        Ok(DigestSettings{
+            cron,
+            realtime: field(obj, "realtime", |v| obj_to_bool_or(v, false))?
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct EmailSettings {
     pub from: String,
     pub to: Vec<String>,
@@ -129,37 +955,455 @@ pub struct EmailSettings {
     pub smtp_port: u16,
     pub smtp_user: String,
     pub smtp_password: String,
-    pub smtp_starttls: bool
+    pub smtp_starttls: bool,
+    pub format: MessageFormat
 }
 
 impl EmailSettings {
-    fn load_from_json_object(obj: &JsonValue) -> Result<EmailSettings, Box<dyn Error>> {
+    fn load_from_json_object(obj: &JsonValue) -> Result<EmailSettings, AppError> {
         let settings = EmailSettings{
-            from: obj_to_str(&obj["from"])?,
-            subject: obj_to_str(&obj["subject"])?,
-            smtp_host: obj_to_str(&obj["smtp"]["host"])?,
-            smtp_port: obj_to_u16(&obj["smtp"]["port"])?,
-            smtp_user: obj_to_str(&obj["smtp"]["user"])?,
-            smtp_password: obj_to_str(&obj["smtp"]["password"])?,
-            smtp_starttls: obj_to_bool(&obj["smtp"]["starttls"])?,
-            to: to_str_array(&obj["to"])?
+            from: field(obj, "from", obj_to_str)?,
+            subject: field(obj, "subject", obj_to_str)?,
+            smtp_host: in_path("smtp", field(&obj["smtp"], "host", obj_to_str))?,
+            smtp_port: in_path("smtp", field(&obj["smtp"], "port", obj_to_u16))?,
+            smtp_user: in_path("smtp", field(&obj["smtp"], "user", obj_to_str))?,
+            smtp_password: in_path("smtp", field(&obj["smtp"], "password", obj_to_str))?,
+            smtp_starttls: in_path("smtp", field(&obj["smtp"], "starttls", obj_to_bool))?,
+            to: field(obj, "to", to_str_array)?,
+            format: field(obj, "format", |v| load_message_format(v, MessageFormat::Html))?
         };
+        if settings.from.parse::<Mailbox>().is_err() {
+            return Err(field_error("from", String::from("not a valid e-mail address")));
+        }
+        for (index, to) in settings.to.iter().enumerate() {
+            if to.parse::<Mailbox>().is_err() {
+                return in_path("to", Err(field_error(&format!("[{}]", index), String::from("not a valid e-mail address"))));
+            }
+        }
+        if SmtpTransport::relay(&settings.smtp_host).is_err() {
+            return in_path("smtp", Err(field_error("host", String::from("not a valid host name"))));
+        }
         Ok(settings)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GotifySettings {
     pub url: String,
-    pub application_token: String
+    pub application_token: String,
+    pub format: MessageFormat,
+    pub proxy: Option<String>,
+    pub tls: TlsSettings,
+    pub network: NetworkSettings
 }
 
 impl GotifySettings {
-    fn load_from_json_object(obj: &JsonValue) -> Result<GotifySettings, Box<dyn Error>> {
+    fn load_from_json_object(obj: &JsonValue) -> Result<GotifySettings, AppError> {
         let settings = GotifySettings{
-            url: obj_to_str(&obj["url"])?,
-            application_token: obj_to_str(&obj["application_token"])?
+            url: field(obj, "url", obj_to_str)?,
+            application_token: field(obj, "application_token", obj_to_str)?,
+            format: field(obj, "format", |v| load_message_format(v, MessageFormat::Markdown))?,
+            proxy: field(obj, "proxy", load_proxy)?,
+            tls: TlsSettings::load_from_json_object(obj)?,
+            network: NetworkSettings::load_from_json_object(obj)?
+        };
+        Ok(settings)
+    }
+}
+
+/// Notifications tried in order until one of them delivers the message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailoverSettings {
+    pub notifications: Vec<NotificationSettings>
+}
+
+impl FailoverSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<FailoverSettings, AppError> {
+        field(obj, "notifications", expect_array)?;
+        let mut notifications = Vec::new();
+        for (index, content) in obj["notifications"].members().enumerate() {
+            notifications.push(in_path(&format!("notifications[{}]", index), NotificationSettings::load_from_json_object(content))?);
+        }
+        if notifications.is_empty() {
+            return Err(field_error("notifications", String::from("must not be empty")));
+        }
+        Ok(FailoverSettings{
+            notifications
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlackSettings {
+    pub webhook_url: String,
+    pub channel: Option<String>,
+    /// Language of the labels of the calendar lists and the booking link
+    pub language: Language
+}
+
+impl SlackSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<SlackSettings, AppError> {
+        let settings = SlackSettings{
+            webhook_url: field(obj, "webhook_url", obj_to_str)?,
+            channel: field(obj, "channel", obj_to_opt_str)?,
+            language: field(obj, "language", load_language)?
         };
         Ok(settings)
     }
 }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamsSettings {
+    pub webhook_url: String,
+    /// Language of the label of the booking link
+    pub language: Language
+}
+
+impl TeamsSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<TeamsSettings, AppError> {
+        let settings = TeamsSettings{
+            webhook_url: field(obj, "webhook_url", obj_to_str)?,
+            language: field(obj, "language", load_language)?
+        };
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushbulletSettings {
+    pub access_token: String,
+    /// Push to this device only instead of all devices of the account
+    pub device_iden: Option<String>,
+    /// Language of the marker in front of the title of urgent pushes
+    pub language: Language
+}
+
+impl PushbulletSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<PushbulletSettings, AppError> {
+        let settings = PushbulletSettings{
+            access_token: field(obj, "access_token", obj_to_str)?,
+            device_iden: field(obj, "device_iden", obj_to_opt_str)?,
+            language: field(obj, "language", load_language)?
+        };
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttSettings {
+    pub broker_host: String,
+    pub broker_port: u16,
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub qos: u8,
+    /// First wait after a connection error, it doubles with every further error
+    pub reconnect_delay_secs: u32
+}
+
+impl MqttSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<MqttSettings, AppError> {
+        let settings = MqttSettings{
+            broker_host: field(obj, "broker_host", obj_to_str)?,
+            broker_port: if obj["broker_port"].is_null() {
+                1883
+            } else {
+                field(obj, "broker_port", obj_to_u16)?
+            },
+            topic: field(obj, "topic", obj_to_str)?,
+            username: field(obj, "username", obj_to_opt_str)?,
+            password: field(obj, "password", obj_to_opt_str)?,
+            qos: if obj["qos"].is_null() {
+                0
+            } else {
+                field(obj, "qos", obj_to_u8)?
+            },
+            reconnect_delay_secs: field(obj, "reconnect_delay_secs", |v| obj_to_u32_or(v, 5))?
+        };
+        if settings.qos > 2 {
+            return Err(field_error("qos", String::from("must be 0, 1 or 2")));
+        }
+        if settings.reconnect_delay_secs == 0 {
+            return Err(field_error("reconnect_delay_secs", String::from("must not be 0")));
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwilioSettings {
+    pub account_sid: String,
+    pub auth_token: String,
+    pub from: String,
+    pub to: Vec<String>,
+    pub send_normal: bool
+}
+
+impl TwilioSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<TwilioSettings, AppError> {
+        let settings = TwilioSettings{
+            account_sid: field(obj, "account_sid", obj_to_str)?,
+            auth_token: field(obj, "auth_token", obj_to_str)?,
+            from: field(obj, "from", obj_to_str)?,
+            to: field(obj, "to", to_str_array)?,
+            send_normal: field(obj, "send_normal", |v| obj_to_bool_or(v, false))?
+        };
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MastodonSettings {
+    pub instance_url: String,
+    pub access_token: String,
+    /// Visibility of urgent posts, normal ones are always `unlisted`
+    pub visibility: String,
+    /// Character limit of a post on the instance
+    pub max_chars: u32
+}
+
+impl MastodonSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<MastodonSettings, AppError> {
+        let settings = MastodonSettings{
+            instance_url: String::from(field(obj, "instance_url", obj_to_str)?.trim_end_matches('/')),
+            access_token: field(obj, "access_token", obj_to_str)?,
+            visibility: field(obj, "visibility", obj_to_opt_str)?.unwrap_or_else(|| String::from("public")),
+            max_chars: field(obj, "max_chars", |v| obj_to_u32_or(v, 500))?
+        };
+        if !["public", "unlisted", "private", "direct"].contains(&settings.visibility.as_str()) {
+            return Err(field_error("visibility", format!("unknown visibility \"{}\", expected public, unlisted, private or direct", settings.visibility)));
+        }
+        if settings.max_chars == 0 {
+            return Err(field_error("max_chars", String::from("must not be 0")));
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSettings {
+    pub path: String,
+    pub format: MessageFormat
+}
+
+impl FileSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<FileSettings, AppError> {
+        let settings = FileSettings{
+            path: field(obj, "path", obj_to_str)?,
+            format: field(obj, "format", |v| load_message_format(v, MessageFormat::Plaintext))?
+        };
+        Ok(settings)
+    }
+}
+
+/// Where the Apprise API server takes the notification services from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppriseTarget {
+    /// Configuration stored on the server under this key
+    ConfigKey(String),
+    /// Apprise service URLs passed with every message
+    Urls(Vec<String>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppriseSettings {
+    pub server_url: String,
+    pub target: AppriseTarget,
+    /// Apprise message type of urgent messages, `warning` or `failure`
+    pub urgent_type: String
+}
+
+impl AppriseSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<AppriseSettings, AppError> {
+        let target = match (obj["config_key"].is_null(), obj["urls"].is_null()) {
+            (false, true) => AppriseTarget::ConfigKey(field(obj, "config_key", obj_to_str)?),
+            (true, false) => AppriseTarget::Urls(field(obj, "urls", to_str_array)?),
+            _ => return Err(AppError::Config(String::from("set either config_key or urls")))
+        };
+        let urgent_type = field(obj, "urgent_type", obj_to_opt_str)?.unwrap_or_else(|| String::from("warning"));
+        if urgent_type != "warning" && urgent_type != "failure" {
+            return Err(field_error("urgent_type", String::from("must be warning or failure")));
+        }
+        let settings = AppriseSettings{
+            server_url: String::from(field(obj, "server_url", obj_to_str)?.trim_end_matches('/')),
+            target,
+            urgent_type
+        };
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyslogTransport {
+    /// Local syslog daemon via its Unix socket
+    Local,
+    Udp(String),
+    Tcp(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyslogSettings {
+    pub facility: String,
+    pub transport: SyslogTransport
+}
+
+impl SyslogSettings {
+    fn load_from_json_object(obj: &JsonValue) -> Result<SyslogSettings, AppError> {
+        let facility = field(obj, "facility", obj_to_opt_str)?.unwrap_or_else(|| String::from("user"));
+        if syslog::Facility::from_str(facility.as_str()).is_err() {
+            return Err(field_error("facility", format!("unknown syslog facility \"{}\"", facility)));
+        }
+        // Remote addresses are given as udp://host:port or tcp://host:port
+        let transport = match field(obj, "address", obj_to_opt_str)? {
+            None => SyslogTransport::Local,
+            Some(address) => match address.split_once("://") {
+                Some(("udp", server)) => SyslogTransport::Udp(String::from(server)),
+                Some(("tcp", server)) => SyslogTransport::Tcp(String::from(server)),
+                Some(_) => return Err(field_error("address", String::from("must use udp:// or tcp://"))),
+                None => SyslogTransport::Udp(address)
+            }
+        };
+        let settings = SyslogSettings{
+            facility,
+            transport
+        };
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopSettings {}
+
+impl DesktopSettings {
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    fn load_from_json_object(_obj: &JsonValue) -> Result<DesktopSettings, AppError> {
+        Ok(DesktopSettings{})
+    }
+}
+
+fn load_message_format(obj: &JsonValue, default: MessageFormat) -> Result<MessageFormat, AppError> {
+    if obj.is_null() {
+        return Ok(default);
+    }
+    match MessageFormat::from_name(obj_to_str(obj)?.as_str()) {
+        Some(format) => Ok(format),
+        None => Err(AppError::Config(String::from("expected markdown, html or plaintext")))
+    }
+}
+
+fn load_language(obj: &JsonValue) -> Result<Language, AppError> {
+    if obj.is_null() {
+        return Ok(Language::default());
+    }
+    let name = obj_to_str(obj)?;
+    match Language::from_name(&name) {
+        Some(language) => Ok(language),
+        None => Err(AppError::Config(format!("unknown language \"{}\", expected en or de", name)))
+    }
+}
+
+fn load_severity(obj: &JsonValue, default: Severity) -> Result<Severity, AppError> {
+    if obj.is_null() {
+        return Ok(default);
+    }
+    match Severity::from_name(obj_to_str(obj)?.as_str()) {
+        Some(severity) => Ok(severity),
+        None => Err(AppError::Config(String::from("expected normal or urgent")))
+    }
+}
+
+/// Reads a single URL or a non-empty list of URLs.
+fn load_urls(obj: &JsonValue) -> Result<Vec<String>, AppError> {
+    if !obj.is_array() {
+        return Ok(vec![obj_to_str(obj)?]);
+    }
+    let urls = to_str_array(obj)?;
+    if urls.is_empty() {
+        return Err(AppError::Config(String::from("must not be empty")));
+    }
+    Ok(urls)
+}
+
+fn load_proxy(obj: &JsonValue) -> Result<Option<String>, AppError> {
+    let proxy = obj_to_opt_str(obj)?;
+    if let Some(url) = &proxy {
+        if reqwest::Proxy::all(url.as_str()).is_err() {
+            return Err(AppError::Config(String::from("not a valid proxy URL")));
+        }
+    }
+    Ok(proxy)
+}
+
+/// SOCKS5 proxy of Tor's default client configuration
+const TOR_SOCKS5_ADDRESS: &str = "127.0.0.1:9050";
+
+/// Reads the `socks5` address (`host:port`) or the `tor` shortcut of a provider.
+///
+/// Host names are resolved by the proxy, so DNS requests do not leak past it.
+/// Tor uses a new circuit, and usually a new exit IP, for connections opened
+/// after about ten minutes. Connections kept alive in between stay on their circuit.
+fn load_socks5_proxy(obj: &JsonValue) -> Result<Option<String>, AppError> {
+    let address = match (field(obj, "socks5", obj_to_opt_str)?, field(obj, "tor", |v| obj_to_bool_or(v, false))?) {
+        (Some(_), true) => return Err(field_error("tor", String::from("must not be combined with socks5"))),
+        (Some(address), false) => address,
+        (None, true) => String::from(TOR_SOCKS5_ADDRESS),
+        (None, false) => return Ok(None)
+    };
+    let url = format!("socks5h://{}", address);
+    if reqwest::Proxy::all(url.as_str()).is_err() {
+        return Err(field_error("socks5", String::from("not a valid host:port address")));
+    }
+    Ok(Some(url))
+}
+
+fn load_headers(obj: &JsonValue) -> Result<HeaderMap, AppError> {
+    let mut headers = HeaderMap::new();
+    for (key, value) in obj.entries() {
+        let name = match HeaderName::from_str(key) {
+            Ok(name) => name,
+            Err(_) => return Err(AppError::Config(format!("invalid header name {}", key)))
+        };
+        let value = match HeaderValue::from_str(in_path(key, obj_to_str(value))?.as_str()) {
+            Ok(value) => value,
+            Err(_) => return Err(field_error(key, String::from("invalid header value")))
+        };
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_name_the_field_path() {
+        let config = json::parse(r#"{
+            "admin_notifications": [],
+            "services": [],
+            "notifications": {
+                "home": { "provider": "gotify", "settings": { "application_token": "x" } }
+            }
+        }"#).unwrap();
+        let error = Config::load_from_json_object(&config).unwrap_err();
+        assert_eq!(error.to_string(), "Config parsing error: notifications.home.settings.url: expected a string, found null");
+    }
+
+    #[test]
+    fn tor_shortcut_sets_socks5_proxy() {
+        let settings = Booked4usSettings::load_from_json_object(&json::parse(r#"{"url": "https://x", "tor": true}"#).unwrap()).unwrap();
+        assert_eq!(settings.proxy, Some(String::from("socks5h://127.0.0.1:9050")));
+
+        let both = json::parse(r#"{"url": "https://x", "proxy": "http://proxy:3128", "socks5": "proxy:1080"}"#).unwrap();
+        assert!(Booked4usSettings::load_from_json_object(&both).is_err());
+    }
+
+    #[test]
+    fn ip_version_binds_to_the_unspecified_address() {
+        let settings = Booked4usSettings::load_from_json_object(&json::parse(r#"{"url": "https://x", "ip_version": "ipv6"}"#).unwrap()).unwrap();
+        assert_eq!(settings.network.local_address, Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)));
+
+        let both = json::parse(r#"{"url": "https://x", "ip_version": "ipv4", "local_address": "192.0.2.1"}"#).unwrap();
+        assert!(Booked4usSettings::load_from_json_object(&both).is_err());
+    }
+}
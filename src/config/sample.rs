@@ -0,0 +1,281 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use json::{array, object, JsonValue};
+
+/// Builds an example config using every service and notification provider.
+///
+/// Keys starting with `_comment` are ignored by the parser and explain the
+/// neighbouring fields. Optional fields are filled in with their defaults.
+pub fn sample_config() -> JsonValue {
+    let mut config = object!{
+        "_comment" => "Example configuration. Replace the <PLACEHOLDERS> and remove the providers you do not need.",
+        "admin_notifications" => object!{
+            "_comment" => "Either a list of notifications for all admin messages or separate lists per kind",
+            "errors" => array!["gotify"],
+            "lifecycle" => array!["file"]
+        },
+        "_comment_admin_title" => "Optional: title of the admin messages, e.g. to tell several pollers apart",
+        "admin_title" => "COVID Vaccination Poll - Admin",
+        "default_sleep" => 120,
+        "shutdown_timeout_secs" => 30,
+        "http_timeout_secs" => 60,
+        "self_check" => false,
+        "_comment_single_threaded" => "Optional: poll all services from one thread, taking turns, e.g. on a Raspberry Pi",
+        "single_threaded" => false,
+        "_comment_heartbeat_secs" => "Optional: interval of a \"still running\" message to the lifecycle admin notifications",
+        "heartbeat_secs" => 86400,
+        "_comment_ping_url" => "Optional: requested every ping_interval_secs while all services work, e.g. a healthchecks.io check",
+        "ping_url" => "https://hc-ping.com/<UUID>",
+        "ping_interval_secs" => 60,
+        "_comment_events_file" => "Optional: JSON Lines file with every poll, change and notification",
+        "events_file" => "events.jsonl",
+        "restart_debounce" => object!{
+            "_comment" => "Optional: no start and stop messages for runs shorter than min_run_secs",
+            "state_file" => "covid-vacc-poll.state",
+            "min_run_secs" => 300
+        },
+        "log_file" => object!{
+            "path" => "covid-vacc-poll.log",
+            "max_size_bytes" => 10485760,
+            "keep" => 5
+        },
+        "services" => array![
+            object!{
+                "_comment" => "Set either sleep (falls back to default_sleep) or a cron expression",
+                "provider" => "booked4us",
+                "title" => "<TITLE>",
+                "enabled" => true,
+                "sleep" => 120,
+                "jitter_secs" => 10,
+                "_comment_start_offset_secs" => "Optional: delay of the first poll, to spread out services with the same sleep",
+                "start_offset_secs" => 0,
+                "_comment_tags" => "Optional: attached to the notifications, e.g. as hashtags, to filter them on the receiving side",
+                "tags" => array!["Berlin"],
+                "_comment_max_consecutive_failures" => "Optional: pause polling for circuit_reset_secs after this many failed polls in a row",
+                "max_consecutive_failures" => 10,
+                "circuit_reset_secs" => 3600,
+                "_comment_notify_fail_fast" => "Stop at the first failed notification instead of trying the remaining ones",
+                "notify_fail_fast" => false,
+                "notifications" => array!["gotify", "slack"],
+                "settings" => object!{
+                    "_comment_url" => "A single URL or a list of URLs whose calendars are merged",
+                    "url" => "https://<URL>",
+                    "api_base_path" => "/rest-v2/api",
+                    "user_agent" => "covid-vacc-poll",
+                    "_comment_proxy" => "Optional: \"proxy\" URL, \"socks5\": \"host:port\" or \"tor\": true for Tor at 127.0.0.1:9050",
+                    "tor" => false,
+                    "_comment_ip_version" => "any, ipv4 or ipv6. Alternatively \"local_address\" binds the connections to an address of this host",
+                    "ip_version" => "any",
+                    "headers" => object!{},
+                    "_comment_message_mode" => "full, added_only or summary",
+                    "message_mode" => "full",
+                    "_comment_language" => "Language of the messages, en or de",
+                    "language" => "de",
+                    "_comment_notify_on_removed" => "Also send an urgent message when a calendar is no longer free",
+                    "notify_on_removed" => false,
+                    "_comment_watch_ids" => "Optional: only poll the calendars with these IDs",
+                    "watch_ids" => array![1, 2],
+                    "_comment_important_ids" => "Optional: calendars which always cause an urgent message with the highest priority when they become free",
+                    "important_ids" => array![1],
+                    "_comment_inter_request_delay_ms" => "Pause between the free slot requests of the calendars, in case the server limits the request rate",
+                    "inter_request_delay_ms" => 0,
+                    "_comment_silent_first_poll" => "Only record the calendars which are free at startup instead of announcing them",
+                    "silent_first_poll" => false,
+                    "_comment_booking_url_template" => "Optional link per calendar, {url} and {id} are replaced",
+                    "booking_url_template" => "{url}/booking/{id}",
+                    "_comment_auth" => "Optional: {\"type\": \"bearer\", \"token\": ...} or {\"type\": \"basic\", \"user\": ..., \"password\": ...}",
+                    "_comment_login" => "Optional: {\"login_url\": ..., \"username\": ..., \"password\": ...} posts a login form and keeps its session cookie"
+                }
+            },
+            object!{
+                "_comment" => "Every day with free slots counts as a free calendar",
+                "provider" => "doctolib",
+                "title" => "<OTHER TITLE>",
+                "sleep" => 120,
+                "notifications" => array!["gotify"],
+                "settings" => object!{
+                    "url" => "https://www.doctolib.de",
+                    "booking_url" => "https://www.doctolib.de/<PRACTICE PAGE>",
+                    "_comment_ids" => "Found in the availabilities.json requests of the booking page",
+                    "visit_motive_ids" => array![1],
+                    "agenda_ids" => array![1],
+                    "practice_ids" => array![],
+                    "_comment_start_date" => "Optional: first polled day, today if omitted",
+                    "days" => 7,
+                    "language" => "de"
+                }
+            }
+        ],
+        "notifications" => object!{
+            "email" => object!{
+                "provider" => "email",
+                "settings" => object!{
+                    "smtp" => object!{
+                        "host" => "mail.example.com",
+                        "port" => 587,
+                        "user" => "<USER>",
+                        "password" => "<PASSWORD>",
+                        "starttls" => true
+                    },
+                    "from" => "poll@example.com",
+                    "to" => array!["me@example.com"],
+                    "subject" => "COVID Vaccination Poll",
+                    "format" => "html"
+                }
+            },
+            "gotify" => object!{
+                "_comment" => "max_retries, retry_delay_secs, min_severity, force_severity, max_per_minute, max_length, prefix, suffix and enabled apply to every notification",
+                "provider" => "gotify",
+                "max_retries" => 3,
+                "retry_delay_secs" => 2,
+                "min_severity" => "normal",
+                "enabled" => true,
+                "settings" => object!{
+                    "url" => "https://<GOTIFY URL>",
+                    "application_token" => "<TOKEN>",
+                    "format" => "markdown"
+                }
+            },
+            "slack" => object!{
+                "provider" => "slack",
+                "settings" => object!{
+                    "webhook_url" => "https://hooks.slack.com/services/<WEBHOOK>",
+                    "channel" => "#vaccination",
+                    "_comment_language" => "Optional: en or de (default), language of the labels",
+                    "language" => "en"
+                }
+            },
+            "mqtt" => object!{
+                "provider" => "mqtt",
+                "settings" => object!{
+                    "broker_host" => "localhost",
+                    "broker_port" => 1883,
+                    "topic" => "covid-vacc-poll",
+                    "qos" => 1,
+                    "_comment_reconnect_delay_secs" => "Optional: first wait after a lost connection, doubles up to 5 minutes",
+                    "reconnect_delay_secs" => 5
+                }
+            },
+            "twilio" => object!{
+                "provider" => "twilio",
+                "min_severity" => "urgent",
+                "_comment_max_length" => "Optional: longer messages are shortened, providers like Twilio also have their own limit",
+                "max_length" => 800,
+                "settings" => object!{
+                    "account_sid" => "<ACCOUNT SID>",
+                    "auth_token" => "<AUTH TOKEN>",
+                    "from" => "+15550000000",
+                    "to" => array!["+15551111111"],
+                    "send_normal" => false
+                }
+            },
+            "file" => object!{
+                "provider" => "file",
+                "_comment_digest" => "Optional: collect the messages and send them as one summary at the cron times, realtime also sends them right away",
+                "digest" => object!{
+                    "cron" => "0 0 8 * * *",
+                    "realtime" => false
+                },
+                "_comment_qr_code" => "Optional: append QR codes of the booking links to markdown and HTML messages, requires the qr feature and a provider which shows images, like file",
+                "qr_code" => false,
+                "settings" => object!{
+                    "path" => "events.log",
+                    "format" => "plaintext"
+                }
+            },
+            "apprise" => object!{
+                "provider" => "apprise",
+                "settings" => object!{
+                    "_comment" => "Set either config_key or urls",
+                    "server_url" => "http://localhost:8000",
+                    "config_key" => "<KEY>",
+                    "urgent_type" => "warning"
+                }
+            },
+            "syslog" => object!{
+                "provider" => "syslog",
+                "settings" => object!{
+                    "_comment" => "Omit address to log to the local syslog daemon",
+                    "facility" => "user",
+                    "address" => "udp://localhost:514"
+                }
+            },
+            "mastodon" => object!{
+                "provider" => "mastodon",
+                "min_severity" => "urgent",
+                "settings" => object!{
+                    "instance_url" => "https://<INSTANCE>",
+                    "access_token" => "<TOKEN>",
+                    "visibility" => "public",
+                    "max_chars" => 500
+                }
+            },
+            "teams" => object!{
+                "provider" => "teams",
+                "settings" => object!{
+                    "webhook_url" => "https://<TENANT>.webhook.office.com/webhookb2/<WEBHOOK>",
+                    "_comment_language" => "Optional: en or de (default), language of the booking link",
+                    "language" => "en"
+                }
+            },
+            "pushbullet" => object!{
+                "provider" => "pushbullet",
+                "settings" => object!{
+                    "_comment" => "Omit device_iden to push to all devices",
+                    "access_token" => "<TOKEN>",
+                    "device_iden" => "<DEVICE>",
+                    "_comment_language" => "Optional: en or de (default), language of the urgent marker",
+                    "language" => "en"
+                }
+            },
+            "failover" => object!{
+                "provider" => "failover",
+                "settings" => object!{
+                    "_comment" => "Tries the notifications in order until one of them works, each is defined like a top-level notification",
+                    "notifications" => array![
+                        object!{
+                            "provider" => "gotify",
+                            "max_retries" => 1,
+                            "settings" => object!{
+                                "url" => "https://gotify.example.com",
+                                "application_token" => "<TOKEN>"
+                            }
+                        },
+                        object!{
+                            "provider" => "file",
+                            "settings" => object!{
+                                "path" => "appointments.txt"
+                            }
+                        }
+                    ]
+                }
+            }
+        }
+    };
+    if cfg!(feature = "desktop") {
+        config["notifications"]["desktop"] = object!{
+            "provider" => "desktop",
+            "settings" => object!{}
+        };
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn sample_config_is_valid() {
+        let config = Config::load_from_json_object(&sample_config()).unwrap();
+        assert_eq!(config.services.len(), 2);
+        assert!(config.notifications.len() >= 8);
+    }
+}
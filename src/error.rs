@@ -10,8 +10,32 @@ use std::error::Error;
 use std::fmt::Display;
 use std::fmt;
 
+/// Errors of the poller, grouped by their origin.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("Config parsing error: {0}")]
+    Config(String),
+    /// Invalid value of the config field at `path`, e.g. `notifications.home.settings.url`
+    #[error("Config parsing error: {path}: {msg}")]
+    ConfigField { path: String, msg: String },
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Notification error: {0}")]
+    Notification(String),
+    #[error("Poll error: {0}")]
+    Poll(String),
+    #[error(transparent)]
+    Generic(#[from] GenericError)
+}
+
+/// An error labelled with the context it occurred in, e.g. "mutex poisoned".
 #[derive(Debug)]
 pub struct GenericError {
+    context: String,
     msg: String
 }
 
@@ -19,12 +43,12 @@ impl Error for GenericError {}
 
 impl Display for GenericError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Config parsing error: {}", self.msg)
+        write!(f, "{}: {}", self.context, self.msg)
     }
 }
 
 impl GenericError {
-    pub fn new(s: &str) -> Box<Self> {
-        Box::new(Self{msg: String::from(s)})
+    pub fn new(context: &str, s: &str) -> Self {
+        Self{context: String::from(context), msg: String::from(s)}
     }
 }
@@ -0,0 +1,61 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use chrono::Local;
+use json::{object, JsonValue};
+use log::warn;
+use crate::error::AppError;
+
+/// Appends one JSON object per line for every poll, change and notification.
+///
+/// Each event has the fields `time`, `event` and `service`, followed by the
+/// fields of the event. A disabled log drops all events.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    file: Option<Arc<Mutex<File>>>
+}
+
+impl EventLog {
+    pub fn open(path: &str) -> Result<EventLog, AppError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(EventLog{
+            file: Some(Arc::new(Mutex::new(file)))
+        })
+    }
+
+    pub fn disabled() -> EventLog {
+        EventLog::default()
+    }
+
+    /// Writes an event, `fields` must be a JSON object.
+    pub fn emit(&self, event: &str, service: &str, fields: JsonValue) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return
+        };
+        let mut line = object!{
+            "time" => Local::now().to_rfc3339(),
+            "event" => event,
+            "service" => service
+        };
+        for (key, value) in fields.entries() {
+            line[key] = value.clone();
+        }
+        let mut file = match file.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        // A full disk must not stop the polling
+        if let Err(error) = writeln!(file, "{}", line.dump()) {
+            warn!("Could not write event: {}", error);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use log::{info, error};
+use json::{object, JsonValue};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::service::{Calendar, PollReport, PollResult, ServiceHandle, TriggeredPoll};
+
+/// Handles of the currently running services, keyed by title.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceRegistry {
+    services: Arc<Mutex<HashMap<String, ServiceHandle>>>
+}
+
+impl ServiceRegistry {
+    pub fn insert(&self, title: &str, handle: ServiceHandle) {
+        self.lock().insert(String::from(title), handle);
+    }
+
+    pub fn remove(&self, title: &str) {
+        self.lock().remove(title);
+    }
+
+    fn get(&self, title: &str) -> Option<ServiceHandle> {
+        self.lock().get(title).cloned()
+    }
+
+    /// Titles of the services which stopped or missed their deadline.
+    pub fn dead_services(&self) -> Vec<String> {
+        let mut dead: Vec<String> = self.lock().iter()
+            .filter(|(_, handle)| !handle.is_alive())
+            .map(|(title, _)| title.clone())
+            .collect();
+        dead.sort();
+        dead
+    }
+
+    /// Whether all services are alive and their last polls succeeded.
+    ///
+    /// Services which have not polled yet count as healthy.
+    pub fn all_healthy(&self) -> bool {
+        self.lock().values().all(|handle| handle.is_alive() && handle.last_poll().is_none_or(|last| last.ok))
+    }
+
+    /// Describes the last poll of every service, for the heartbeat message.
+    pub fn poll_report(&self) -> String {
+        let mut lines: Vec<String> = self.lock().iter()
+            .map(|(title, handle)| match handle.last_poll() {
+                Some(last) => format!("* {}: last poll {} {}", title, last.time.format("%Y-%m-%d %H:%M:%S"),
+                                      if last.ok { "OK" } else { "failed" }),
+                None => format!("* {}: not polled yet", title)
+            })
+            .collect();
+        lines.sort();
+        lines.join("\n")
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, ServiceHandle>> {
+        match self.services.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+}
+
+/// Small HTTP API for container orchestrators and scripts.
+///
+/// * `GET /healthz` responds with 200 while all services are alive and with
+///   503 listing the dead services otherwise.
+/// * `POST /poll/{title}` polls the service immediately and responds with
+///   the result. It is only served if `poll_trigger` is enabled. While a
+///   triggered poll is running, further ones are answered with 429.
+pub struct ApiServer {
+    server: Arc<Server>,
+    thrd: thread::JoinHandle<()>
+}
+
+impl ApiServer {
+    pub fn start(address: IpAddr, port: u16, registry: ServiceRegistry, poll_trigger: bool) -> Result<ApiServer, String> {
+        let server = Arc::new(Server::http((address, port)).map_err(|error| error.to_string())?);
+        info!("HTTP API listening on {}:{}", address, port);
+        let thrd = {
+            let server = server.clone();
+            thread::spawn(move || {
+                let polling = Arc::new(AtomicBool::new(false));
+                for request in server.incoming_requests() {
+                    Self::handle(request, &registry, poll_trigger, &polling);
+                }
+            })
+        };
+        Ok(ApiServer{
+            server,
+            thrd
+        })
+    }
+
+    fn handle(request: Request, registry: &ServiceRegistry, poll_trigger: bool, polling: &Arc<AtomicBool>) {
+        let url = String::from(request.url());
+        let response = match (request.method(), url.strip_prefix("/poll/")) {
+            (Method::Get, _) if url == "/healthz" => {
+                let dead = registry.dead_services();
+                if dead.is_empty() {
+                    Response::from_string("OK")
+                } else {
+                    Response::from_string(format!("Dead services: {}", dead.join(", "))).with_status_code(503)
+                }
+            },
+            (Method::Post, Some(_)) if poll_trigger && polling.swap(true, Ordering::SeqCst) => {
+                Response::from_string("A triggered poll is already running").with_status_code(429)
+            },
+            (Method::Post, Some(title)) if poll_trigger => {
+                // A triggered poll takes a while, so it must not block the health check
+                let title = percent_decode(title);
+                let registry = registry.clone();
+                let polling = polling.clone();
+                thread::spawn(move || {
+                    Self::respond(request, Self::poll(&registry, title.as_str()));
+                    polling.store(false, Ordering::SeqCst);
+                });
+                return;
+            },
+            _ => Response::from_string("Not Found").with_status_code(404)
+        };
+        Self::respond(request, response);
+    }
+
+    fn respond(request: Request, response: Response<std::io::Cursor<Vec<u8>>>) {
+        if let Err(error) = request.respond(response) {
+            error!("Could not answer HTTP request: {}", error);
+        }
+    }
+
+    fn poll(registry: &ServiceRegistry, title: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+        let handle = match registry.get(title) {
+            Some(handle) => handle,
+            None => return Response::from_string(format!("Unknown service {}", title)).with_status_code(404)
+        };
+        let (status, body) = match handle.poll_now() {
+            Ok(TriggeredPoll{result: PollResult::None, ..}) => (200, object!{"result" => "none"}),
+            Ok(TriggeredPoll{result: PollResult::Normal(report), message}) => (200, report_to_json("normal", &report, message)),
+            Ok(TriggeredPoll{result: PollResult::Urgent(report), message}) => (200, report_to_json("urgent", &report, message)),
+            Err(msg) => (502, object!{"error" => msg})
+        };
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+        Response::from_string(body.dump()).with_status_code(status).with_header(content_type)
+    }
+
+    pub fn stop(self) {
+        self.server.unblock();
+        let _ = self.thrd.join();
+    }
+}
+
+/// Lists the calendars of a report next to the message sent about it.
+fn report_to_json(result: &str, report: &PollReport, message: Option<String>) -> JsonValue {
+    let calendars = |calendars: &[Calendar]| -> JsonValue {
+        calendars.iter()
+            .map(|calendar| object!{"id" => calendar.id, "name" => calendar.name.as_str()})
+            .collect::<Vec<JsonValue>>()
+            .into()
+    };
+    object!{
+        "result" => result,
+        "message" => message,
+        "added" => calendars(&report.added),
+        "all_free" => calendars(&report.all_free),
+        "removed" => calendars(&report.removed)
+    }
+}
+
+/// Decodes `%XX` escapes of a URL path segment, e.g. spaces in a service title.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
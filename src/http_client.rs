@@ -0,0 +1,107 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use log::warn;
+use crate::config::{NetworkSettings, TlsSettings};
+use crate::error::AppError;
+
+/// Number of characters of a response included in an error message
+const MAX_ERROR_BODY_LENGTH: usize = 200;
+
+/// Connection settings which require a separate client: proxy, TLS and network.
+type ClientKey = (Option<String>, TlsSettings, NetworkSettings);
+
+/// HTTP clients shared by all services and notificators.
+///
+/// Each client has its own connection pool, so a single client is created per
+/// distinct proxy, TLS and network setting. Without an explicit proxy, the
+/// `HTTP_PROXY`/`HTTPS_PROXY` environment variables are respected.
+#[derive(Debug, Clone)]
+pub struct ClientPool {
+    timeout: Duration,
+    clients: Arc<Mutex<HashMap<ClientKey, reqwest::Client>>>
+}
+
+impl ClientPool {
+    /// Every request of the created clients is aborted after `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        ClientPool{
+            timeout,
+            clients: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    /// Returns the client for requests to `url`, creating it on first use.
+    pub fn get(&self, url: &str, proxy: &Option<String>, tls: &TlsSettings, network: &NetworkSettings) -> reqwest::Client {
+        if tls.insecure {
+            warn!("TLS certificate verification is DISABLED for {}. Connections can be intercepted.", url);
+        }
+        // A client build which panicked left the map unchanged
+        let mut clients = match self.clients.lock() {
+            Ok(clients) => clients,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        clients.entry((proxy.clone(), tls.clone(), network.clone()))
+            .or_insert_with(|| build_client(self.timeout, proxy, tls, network, false))
+            .clone()
+    }
+
+    /// Creates a client with its own cookie store, e.g. for a login session.
+    ///
+    /// It is not shared, so the session cookies are not sent to other servers.
+    pub fn session(&self, url: &str, proxy: &Option<String>, tls: &TlsSettings, network: &NetworkSettings) -> reqwest::Client {
+        if tls.insecure {
+            warn!("TLS certificate verification is DISABLED for {}. Connections can be intercepted.", url);
+        }
+        build_client(self.timeout, proxy, tls, network, true)
+    }
+
+    /// Client without proxy and with the default certificate verification.
+    pub fn default_client(&self) -> reqwest::Client {
+        self.get("", &None, &TlsSettings::default(), &NetworkSettings::default())
+    }
+}
+
+fn build_client(timeout: Duration, proxy: &Option<String>, tls: &TlsSettings, network: &NetworkSettings, cookies: bool) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder().timeout(timeout).cookie_store(cookies);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.as_str()).expect("Proxy URL is validated when loading the config"));
+    }
+    if let Some(path) = &tls.ca_cert_path {
+        builder = builder.add_root_certificate(load_certificate(path).expect("CA certificate is validated when loading the config"));
+    }
+    if tls.insecure {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    // Binding to an address only connects to resolved addresses of the same IP version
+    if let Some(address) = network.local_address {
+        builder = builder.local_address(address);
+    }
+    builder.build().expect("HTTP client could not be initialized")
+}
+
+/// Start of a response body or document, short enough for an error message.
+pub fn excerpt(body: &str) -> String {
+    body.chars().take(MAX_ERROR_BODY_LENGTH).collect()
+}
+
+/// Describes a failed request by its status and the start of the response body.
+pub fn status_error(status: reqwest::StatusCode, body: &str) -> String {
+    format!("HTTP {}: {}", status, excerpt(body))
+}
+
+/// Reads a PEM encoded certificate.
+pub fn load_certificate(path: &str) -> Result<reqwest::Certificate, AppError> {
+    let pem = fs::read(path)?;
+    let cert = reqwest::Certificate::from_pem(&pem)?;
+    Ok(cert)
+}
@@ -0,0 +1,99 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Built-in texts of the poll messages.
+
+/// Language of the built-in message texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    En,
+    De
+}
+
+impl Language {
+    pub fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "en" => Some(Language::En),
+            "de" => Some(Language::De),
+            _ => None
+        }
+    }
+
+    pub fn texts(&self) -> &'static Texts {
+        match self {
+            Language::En => &EN,
+            Language::De => &DE
+        }
+    }
+}
+
+impl Default for Language {
+    /// German, the language the messages were written in originally.
+    fn default() -> Self {
+        Language::De
+    }
+}
+
+/// Message texts of one language.
+#[derive(Debug)]
+pub struct Texts {
+    /// Heading of the newly freed calendars
+    pub added: &'static str,
+    /// Heading of all free calendars
+    pub all_free: &'static str,
+    /// Heading of the calendars which are no longer free
+    pub removed: &'static str,
+    /// Counts of the summary, with the placeholders `{added}`, `{all_free}` and `{removed}`
+    pub summary: &'static str,
+    /// First line of urgent messages, with the placeholders `{added}` and `{all_free}`
+    pub headline: &'static str,
+    /// Heading of the newly freed days of providers listing slots per day
+    pub added_days: &'static str,
+    /// Heading of all free days
+    pub all_free_days: &'static str,
+    /// Number of free slots of a day, with the placeholder `{count}`
+    pub slots: &'static str,
+    /// Put in front of the title of urgent messages by notificators without priorities
+    pub urgent_prefix: &'static str,
+    /// Label of the newly freed calendars in notificators with their own layout
+    pub newly_free: &'static str,
+    /// Label of the calendars which are no longer free in notificators with their own layout
+    pub no_longer_free: &'static str,
+    /// Label of the link to the booking page
+    pub book_now: &'static str
+}
+
+const EN: Texts = Texts{
+    added: "Newly free categories:",
+    all_free: "All free categories:",
+    removed: "No longer free:",
+    summary: "Newly free: {added}, all free: {all_free}, no longer free: {removed}",
+    headline: "{added} new / {all_free} total free",
+    added_days: "Newly free days:",
+    all_free_days: "All free days:",
+    slots: "{count} free slots",
+    urgent_prefix: "Urgent: ",
+    newly_free: "Newly free",
+    no_longer_free: "No longer free",
+    book_now: "Book now"
+};
+
+const DE: Texts = Texts{
+    added: "Frei gewordene Kategorien:",
+    all_free: "Alle freien Kategorien:",
+    removed: "Nicht mehr frei:",
+    summary: "Frei geworden: {added}, alle freien: {all_free}, nicht mehr frei: {removed}",
+    headline: "{added} neu / {all_free} insgesamt frei",
+    added_days: "Frei gewordene Tage:",
+    all_free_days: "Alle freien Tage:",
+    slots: "{count} freie Termine",
+    urgent_prefix: "Dringend: ",
+    newly_free: "Frei geworden",
+    no_longer_free: "Nicht mehr frei",
+    book_now: "Zur Buchung"
+};
@@ -6,66 +6,196 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::fmt;
-use std::error::Error;
 use json::JsonValue;
+use crate::error::AppError;
+use crate::http_client::excerpt;
 
-#[derive(Debug)]
-pub struct ParseError {
-    msg: String
+/// Returns the array `key` of a polled document, `what` names the document in the error.
+///
+/// `members()` of anything but an array is empty, which would look like
+/// nothing being free at all, so a missing array is an error.
+pub fn response_array<'a>(doc: &'a JsonValue, key: &str, what: &str) -> Result<&'a JsonValue, AppError> {
+    if doc[key].is_array() {
+        Ok(&doc[key])
+    } else {
+        Err(AppError::Poll(format!("{} has no {} array: {}", what, key, excerpt(&doc.dump()))))
+    }
+}
+
+/// Loads the field `key` of `obj`, labelling errors with the field name.
+pub fn field<T, F>(obj: &JsonValue, key: &str, load: F) -> Result<T, AppError>
+    where F: FnOnce(&JsonValue) -> Result<T, AppError>
+{
+    in_path(key, load(&obj[key]))
 }
 
-impl Error for ParseError {}
+/// Prepends `segment` to the field path of a config error.
+///
+/// Array indices are given as `[index]` and are joined without a dot.
+pub fn in_path<T>(segment: &str, result: Result<T, AppError>) -> Result<T, AppError> {
+    result.map_err(|error| match error {
+        AppError::Config(msg) => AppError::ConfigField{
+            path: String::from(segment),
+            msg
+        },
+        AppError::ConfigField{path, msg} => AppError::ConfigField{
+            path: if path.starts_with('[') {
+                format!("{}{}", segment, path)
+            } else {
+                format!("{}.{}", segment, path)
+            },
+            msg
+        },
+        other => other
+    })
+}
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Config parsing error: {}", self.msg)
+/// Error about the field `key` of the object being loaded.
+pub fn field_error(key: &str, msg: String) -> AppError {
+    AppError::ConfigField{
+        path: String::from(key),
+        msg
     }
 }
 
-impl ParseError {
-    pub fn new(s: &str) -> Box<ParseError> {
-        Box::new(ParseError{msg: String::from(s)})
+fn type_name(obj: &JsonValue) -> &'static str {
+    match obj {
+        JsonValue::Null => "null",
+        JsonValue::Short(_) | JsonValue::String(_) => "string",
+        JsonValue::Number(_) => "number",
+        JsonValue::Boolean(_) => "boolean",
+        JsonValue::Object(_) => "object",
+        JsonValue::Array(_) => "array"
     }
 }
 
-pub fn obj_to_str(obj: &JsonValue) -> Result<String, Box<dyn Error>> {
+fn unexpected(expected: &str, obj: &JsonValue) -> AppError {
+    match obj {
+        JsonValue::Number(_) => AppError::Config(format!("expected {}, found {}", expected, obj.dump())),
+        _ => AppError::Config(format!("expected {}, found {}", expected, type_name(obj)))
+    }
+}
+
+pub fn expect_array(obj: &JsonValue) -> Result<(), AppError> {
+    if obj.is_array() {
+        Ok(())
+    } else {
+        Err(unexpected("an array", obj))
+    }
+}
+
+pub fn expect_object(obj: &JsonValue) -> Result<(), AppError> {
+    if obj.is_object() {
+        Ok(())
+    } else {
+        Err(unexpected("an object", obj))
+    }
+}
+
+pub fn obj_to_str(obj: &JsonValue) -> Result<String, AppError> {
     match obj.as_str() {
         Some(val) => Ok(String::from(val)),
-        None => return Err(ParseError::new("Could not load string from JSON"))
+        None => Err(unexpected("a string", obj))
+    }
+}
+
+pub fn obj_to_opt_str(obj: &JsonValue) -> Result<Option<String>, AppError> {
+    if obj.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(obj_to_str(obj)?))
     }
 }
 
-pub fn obj_to_bool(obj: &JsonValue) -> Result<bool, Box<dyn Error>> {
+pub fn obj_to_bool(obj: &JsonValue) -> Result<bool, AppError> {
     match obj.as_bool() {
         Some(val) => Ok(val),
-        None => return Err(ParseError::new("Could not load bool from JSON"))
+        None => Err(unexpected("a boolean", obj))
     }
 }
 
-pub fn obj_to_u16(obj: &JsonValue) -> Result<u16, Box<dyn Error>> {
+pub fn obj_to_bool_or(obj: &JsonValue, default: bool) -> Result<bool, AppError> {
+    if obj.is_null() {
+        Ok(default)
+    } else {
+        obj_to_bool(obj)
+    }
+}
+
+pub fn obj_to_u8(obj: &JsonValue) -> Result<u8, AppError> {
+    match obj.as_u8() {
+        Some(val) => Ok(val),
+        None => Err(unexpected("an integer between 0 and 255", obj))
+    }
+}
+
+pub fn obj_to_u16(obj: &JsonValue) -> Result<u16, AppError> {
     match obj.as_u16() {
         Some(val) => Ok(val),
-        None => return Err(ParseError::new("Could not load u16 from JSON"))
+        None => Err(unexpected("an integer between 0 and 65535", obj))
     }
 }
 
-pub fn obj_to_u32(obj: &JsonValue) -> Result<u32, Box<dyn Error>> {
+pub fn obj_to_u32(obj: &JsonValue) -> Result<u32, AppError> {
     match obj.as_u32() {
         Some(val) => Ok(val),
-        None => return Err(ParseError::new("Could not load u32 from JSON"))
+        None => Err(unexpected("a non-negative integer", obj))
+    }
+}
+
+/// Reads an identifier given either as non-negative integer or as numeric string.
+pub fn obj_to_id(obj: &JsonValue) -> Result<u64, AppError> {
+    let id = match obj.as_str() {
+        Some(text) => text.trim().parse().ok(),
+        None => obj.as_u64()
+    };
+    match id {
+        Some(id) => Ok(id),
+        None => Err(unexpected("a non-negative integer or numeric string", obj))
+    }
+}
+
+pub fn obj_to_u32_or(obj: &JsonValue, default: u32) -> Result<u32, AppError> {
+    if obj.is_null() {
+        Ok(default)
+    } else {
+        obj_to_u32(obj)
+    }
+}
+
+pub fn obj_to_opt_u32(obj: &JsonValue) -> Result<Option<u32>, AppError> {
+    if obj.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(obj_to_u32(obj)?))
+    }
+}
+
+pub fn obj_to_f64_or(obj: &JsonValue, default: f64) -> Result<f64, AppError> {
+    if obj.is_null() {
+        return Ok(default);
+    }
+    match obj.as_f64() {
+        Some(val) => Ok(val),
+        None => Err(unexpected("a number", obj))
     }
 }
 
-pub fn to_str_array(obj: &JsonValue) -> Result<Vec<String>, Box<dyn Error>> {
+pub fn to_str_array(obj: &JsonValue) -> Result<Vec<String>, AppError> {
     let mut arr: Vec<String> = Vec::new();
-    for val in obj.members() {
+    for (index, val) in obj.members().enumerate() {
         match val.as_str() {
             Some(v) => arr.push(String::from(v)),
-            None => return Err(ParseError::new("Could not load string array from JSON"))
+            None => return in_path(&format!("[{}]", index), Err(unexpected("a string", val)))
         }
     }
     Ok(arr)
 }
 
-
+pub fn to_id_array(obj: &JsonValue) -> Result<Vec<u64>, AppError> {
+    let mut arr: Vec<u64> = Vec::new();
+    for (index, val) in obj.members().enumerate() {
+        arr.push(in_path(&format!("[{}]", index), obj_to_id(val))?);
+    }
+    Ok(arr)
+}
@@ -0,0 +1,227 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Polls COVID vaccination registration websites for free appointments and
+//! delivers notifications about them.
+//!
+//! The poller can be embedded into other applications by loading a
+//! [`config::Config`] and passing it to [`run`].
+
+pub mod config;
+pub mod notification;
+pub mod service;
+pub mod error;
+mod events;
+pub mod i18n;
+mod http_api;
+mod http_client;
+mod json_helper;
+mod shutdown;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, info, warn, error};
+
+use config::Config;
+use notification::{AdminNotifications, AdminRecipients, NotificatorCollection};
+use service::ServiceCollection;
+use http_api::ApiServer;
+use http_client::ClientPool;
+use events::EventLog;
+
+/// Controls a running poller.
+#[derive(Debug)]
+pub enum Command {
+    /// Stops all services and returns from [`run`].
+    Shutdown,
+    /// Applies a new configuration without restarting unchanged services.
+    ///
+    /// `max_runtime_secs`, `log_file`, `health_port`, `health_bind_address`,
+    /// `poll_trigger`, `events_file`, `admin_title` and `single_threaded` keep
+    /// the values the poller was started with.
+    Reload(Box<Config>)
+}
+
+/// Which of the start and stop messages are sent to the admins.
+///
+/// Keeps a poller which is restarted over and over, e.g. by a supervisor,
+/// from flooding the admins with start and stop messages.
+#[derive(Debug, Clone)]
+pub struct LifecycleNotices {
+    /// Sent when the poller has started, `None` suppresses the message
+    pub start_message: Option<String>,
+    /// The stop message is only sent if the poller ran at least this long
+    pub min_runtime: Duration
+}
+
+impl Default for LifecycleNotices {
+    fn default() -> Self {
+        LifecycleNotices{
+            start_message: Some(String::from("COVID Vaccination Poll App Started")),
+            min_runtime: Duration::ZERO
+        }
+    }
+}
+
+/// Runs all configured services until [`Command::Shutdown`] is received on `control`.
+///
+/// Dropping the sending half of `control` also stops the poller. The function
+/// returns after the services have stopped and the admin notifications have
+/// been flushed.
+pub fn run(config: Config, lifecycle: LifecycleNotices, control: mpsc::Receiver<Command>) {
+    execute(config, false, lifecycle, control)
+}
+
+/// Polls every configured service a single time and returns.
+///
+/// [`Command::Shutdown`] aborts the remaining polls. Reloads are ignored.
+pub fn run_once(config: Config, lifecycle: LifecycleNotices, control: mpsc::Receiver<Command>) {
+    execute(config, true, lifecycle, control)
+}
+
+/// Requests the dead man's switch URL in the background, so a slow monitor cannot stall the poller.
+fn ping(clients: &ClientPool, url: &str) {
+    let client = clients.default_client();
+    let url = String::from(url);
+    thread::spawn(move || {
+        // The request has to be created within the runtime, which provides its timeout
+        match async_std::task::block_on(async { client.get(&url).send().await?.error_for_status() }) {
+            Ok(_) => debug!("Pinged {}", url),
+            Err(error) => warn!("Ping of {} failed: {}", url, error)
+        }
+    });
+}
+
+fn execute(config: Config, once: bool, lifecycle: LifecycleNotices, control: mpsc::Receiver<Command>) {
+    let started = Instant::now();
+    let mut config = config;
+    for warning in config.warnings() {
+        warn!("{}", warning);
+    }
+    // Timeout changes only apply after a restart, like the HTTP connections themselves
+    let clients = ClientPool::new(Duration::from_secs(config.http_timeout_secs as u64));
+    let mut notifs = NotificatorCollection::from(&config, &clients);
+    let admin_notifs = AdminNotifications::new(AdminRecipients::from(&notifs, &config.admin_notifications), &config.admin_title);
+    if config.self_check {
+        let notifs_ok = notifs.self_check(&admin_notifs.get_tx());
+        let services_ok = service::self_check(&config, &clients, &admin_notifs.get_tx());
+        if notifs_ok && services_ok {
+            info!("Self-check passed.");
+        }
+    }
+    let events = match &config.events_file {
+        Some(path) => EventLog::open(path).unwrap_or_else(|error| {
+            let msg = format!("Could not open events file {}: {}", path, error);
+            error!("{}", msg);
+            admin_notifs.get_tx().send("App", msg.as_str());
+            EventLog::disabled()
+        }),
+        None => EventLog::disabled()
+    };
+    let mut services = ServiceCollection::from(&config, &clients, &notifs, &admin_notifs, &events, once);
+
+    let api_server = match config.health_port {
+        Some(port) => match ApiServer::start(config.health_bind_address, port, services.registry(), config.poll_trigger) {
+            Ok(server) => Some(server),
+            Err(msg) => {
+                let msg = format!("Could not start HTTP API on port {}: {}", port, msg);
+                error!("{}", msg);
+                admin_notifs.get_tx().send("App", msg.as_str());
+                None
+            }
+        },
+        None => None
+    };
+
+    if let Some(msg) = &lifecycle.start_message {
+        admin_notifs.get_tx().send_lifecycle("App", msg);
+    }
+
+    let deadline = config.max_runtime_secs.map(|secs| Instant::now() + Duration::from_secs(secs as u64));
+    let mut last_heartbeat = Instant::now();
+    let mut last_ping: Option<Instant> = None;
+    while !services.all_finished() {
+        let wait = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    info!("Maximum runtime of {} s reached. Shutting down.", config.max_runtime_secs.unwrap_or(0));
+                    break;
+                }
+                remaining.min(Duration::from_secs(1))
+            },
+            None => Duration::from_secs(1)
+        };
+        match control.recv_timeout(wait) {
+            Ok(Command::Reload(new_config)) => {
+                if once {
+                    warn!("Ignoring configuration reload while polling once.");
+                    continue;
+                }
+                let changed = notifs.reload(&new_config);
+                let admin = &new_config.admin_notifications;
+                let admin_changed = *admin != config.admin_notifications
+                    || admin.errors.iter().chain(admin.lifecycle.iter()).any(|name| changed.contains(name));
+                if admin_changed {
+                    admin_notifs.set_recipients(AdminRecipients::from(&notifs, admin));
+                }
+                services.reload(&new_config, &notifs, &changed, &admin_notifs);
+                for warning in new_config.warnings() {
+                    warn!("{}", warning);
+                }
+                config = Config{
+                    max_runtime_secs: config.max_runtime_secs,
+                    log_file: config.log_file,
+                    health_port: config.health_port,
+                    health_bind_address: config.health_bind_address,
+                    poll_trigger: config.poll_trigger,
+                    events_file: config.events_file,
+                    admin_title: config.admin_title,
+                    single_threaded: config.single_threaded,
+                    ..*new_config
+                };
+                info!("Configuration reloaded.");
+                admin_notifs.get_tx().send_lifecycle("App", "Configuration reloaded");
+            },
+            // A dropped sender is treated like a shutdown request
+            Ok(Command::Shutdown) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => ()
+        }
+        services.restart_crashed(&notifs, &admin_notifs);
+        if let Some(heartbeat_secs) = config.heartbeat_secs {
+            if !once && last_heartbeat.elapsed() >= Duration::from_secs(heartbeat_secs as u64) {
+                last_heartbeat = Instant::now();
+                let msg = format!("Still running.\n{}", services.registry().poll_report());
+                admin_notifs.get_tx().send_lifecycle("Heartbeat", msg.as_str());
+            }
+        }
+        if let Some(url) = &config.ping_url {
+            let due = last_ping.is_none_or(|last| last.elapsed() >= Duration::from_secs(config.ping_interval_secs as u64));
+            if !once && due && services.registry().all_healthy() {
+                last_ping = Some(Instant::now());
+                ping(&clients, url);
+            }
+        }
+    }
+    services.kill_all();
+    if let Some(server) = api_server {
+        server.stop();
+    }
+
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs as u64);
+    services.join_all(shutdown_timeout);
+    if started.elapsed() >= lifecycle.min_runtime {
+        admin_notifs.get_tx().send_lifecycle("App", "COVID Vaccination Poll App Terminated");
+    } else {
+        info!("Not announcing the termination after a short run.");
+    }
+
+    admin_notifs.get_killer().kill();
+    admin_notifs.join(shutdown_timeout).unwrap();
+}
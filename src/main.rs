@@ -6,18 +6,295 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use crate::notification::AdminNotifications;
+use covid_vacc_poll::config;
+use covid_vacc_poll::config::{LogFileSettings, RestartDebounceSettings, ServiceSettings};
+use covid_vacc_poll::{Command, LifecycleNotices};
+use covid_vacc_poll::error::AppError;
+use simple_logger::SimpleLogger;
+use json::{object, JsonValue};
+use flexi_logger::{Cleanup, Criterion, Duplicate, FileSpec, LogSpecification, Logger, LoggerHandle, Naming};
+use log::{LevelFilter, warn};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use log::{info, error};
+#[cfg(unix)]
+use signal_hook::{consts::{SIGHUP, SIGTERM}, iterator::Signals};
 
-mod config;
-mod notification;
-mod service;
-mod error;
-mod json_helper;
+/// Default log level with overrides per module, e.g. `info,covid_vacc_poll::service=debug`.
+struct LogLevels {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>
+}
 
-use ctrlc;
-use simple_logger::SimpleLogger;
-use log::{LevelFilter};
-use clap;
+impl LogLevels {
+    /// Parses comma separated `level` and `module=level` entries like `env_logger` does.
+    fn parse(spec: &str, default: LevelFilter) -> Result<LogLevels, String> {
+        let mut levels = LogLevels{
+            default,
+            modules: Vec::new()
+        };
+        let parse_level = |level: &str| LevelFilter::from_str(level.trim())
+            .map_err(|_| format!("Invalid log level \"{}\", expected off, error, warn, info, debug or trace", level.trim()));
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            match entry.split_once('=') {
+                Some((module, level)) => levels.modules.push((String::from(module.trim()), parse_level(level)?)),
+                None => levels.default = parse_level(entry)?
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// Logs to stdout and to a file which is rotated by size.
+fn init_file_logger(levels: &LogLevels, settings: &LogFileSettings) -> Result<LoggerHandle, Box<dyn Error>> {
+    // A bare file name has an empty parent, which flexi_logger cannot create
+    let path = Path::new(&settings.path);
+    let path = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Path::new(".").join(path),
+        _ => path.to_path_buf()
+    };
+    let mut spec = LogSpecification::builder();
+    spec.default(levels.default);
+    for (module, level) in levels.modules.iter() {
+        spec.module(module, *level);
+    }
+    let handle = Logger::with(spec.build())
+        .log_to_file(FileSpec::try_from(path)?)
+        .rotate(
+            Criterion::Size(settings.max_size_bytes as u64),
+            Naming::Numbers,
+            Cleanup::KeepLogFiles(settings.keep as usize)
+        )
+        .duplicate_to_stdout(Duplicate::All)
+        .format(flexi_logger::detailed_format)
+        .start()?;
+    Ok(handle)
+}
+
+/// Decides on the start and stop messages using the start time of the previous run.
+///
+/// The state file holds the start time of the last run and the number of
+/// consecutive runs which were shorter than `min_run_secs`, including the
+/// restart delay. The first short run is announced as a restart loop, later
+/// ones stay silent until a run lasts long enough again.
+fn lifecycle_notices(settings: &RestartDebounceSettings) -> LifecycleNotices {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let previous = fs::read_to_string(&settings.state_file).ok().and_then(|content| {
+        let mut values = content.split_whitespace().map(|value| value.parse::<u64>());
+        match (values.next(), values.next()) {
+            (Some(Ok(start)), Some(Ok(short_runs))) => Some((start, short_runs)),
+            _ => None
+        }
+    });
+    let short_runs = match previous {
+        Some((start, short_runs)) if now.saturating_sub(start) < settings.min_run_secs as u64 => short_runs + 1,
+        _ => 0
+    };
+    if let Err(error) = fs::write(&settings.state_file, format!("{} {}\n", now, short_runs)) {
+        warn!("Could not write the state file {}: {}", settings.state_file, error);
+    }
+    let start_message = match short_runs {
+        0 => LifecycleNotices::default().start_message,
+        1 => Some(format!(
+            "COVID Vaccination Poll App is restarting repeatedly. Start and stop messages are suppressed until it runs for {} s.",
+            settings.min_run_secs
+        )),
+        _ => None
+    };
+    LifecycleNotices{
+        start_message,
+        min_runtime: Duration::from_secs(settings.min_run_secs as u64)
+    }
+}
+
+/// Describes when a service polls, e.g. `120 s ± 10 s`.
+fn schedule_text(settings: &ServiceSettings) -> String {
+    match (&settings.cron, &settings.adaptive) {
+        (Some(cron), _) => format!("cron {}", cron),
+        (None, Some(adaptive)) => format!("{}-{} s adaptive", adaptive.min_sleep, adaptive.max_sleep),
+        (None, None) if settings.jitter_secs > 0 => format!("{} s ± {} s", settings.sleep, settings.jitter_secs),
+        (None, None) => format!("{} s", settings.sleep)
+    }
+}
+
+/// Prints a table of the services and the notifications they route to.
+fn list_services(cfg: &config::Config) {
+    let header = ["TITLE", "PROVIDER", "URL", "SCHEDULE", "NOTIFICATIONS"].map(String::from);
+    let mut rows = vec![header];
+    for srv in cfg.services.iter() {
+        let title = if srv.enabled {
+            srv.title.clone()
+        } else {
+            format!("{} (disabled)", srv.title)
+        };
+        rows.push([
+            title,
+            String::from(srv.provider.name()),
+            srv.provider.urls().join(", "),
+            schedule_text(srv),
+            srv.notifications.join(", ")
+        ]);
+    }
+    let mut widths = [0; 5];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows.iter() {
+        let line: Vec<String> = row.iter().zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    println!();
+    println!("Admin errors:    {}", cfg.admin_notifications.errors.join(", "));
+    println!("Admin lifecycle: {}", cfg.admin_notifications.lifecycle.join(", "));
+    for warning in cfg.warnings() {
+        println!("Warning: {}", warning);
+    }
+}
+
+/// Prints the result of checking the configuration and returns whether it is valid.
+///
+/// Unlike a normal start, all problems with the references between the
+/// sections are listed instead of only the first one.
+fn validate(source: &ConfigSource, as_json: bool) -> bool {
+    let cfg = source.read_unvalidated();
+    let validation = match &cfg {
+        Ok(cfg) => cfg.check(),
+        Err(_) => config::Validation::default()
+    };
+    let errors: Vec<String> = match &cfg {
+        Ok(_) => validation.errors(),
+        Err(error) => vec![error.to_string()]
+    };
+    let warnings = match &cfg {
+        Ok(cfg) => cfg.warnings(),
+        Err(_) => Vec::new()
+    };
+    let ok = errors.is_empty();
+    if as_json {
+        let services: Vec<JsonValue> = cfg.iter()
+            .flat_map(|cfg| cfg.services.iter())
+            .map(|srv| object!{
+                "title" => srv.title.as_str(),
+                "provider" => srv.provider.name(),
+                "enabled" => srv.enabled,
+                "notifications" => srv.notifications.clone()
+            })
+            .collect();
+        let dangling: Vec<JsonValue> = validation.dangling_references.iter()
+            .map(|reference| object!{
+                "notification" => reference.notification.as_str(),
+                "referenced_by" => reference.referenced_by.as_str()
+            })
+            .collect();
+        println!("{}", object!{
+            "ok" => ok,
+            "errors" => errors,
+            "warnings" => warnings,
+            "dangling_references" => dangling,
+            "duplicate_titles" => validation.duplicate_titles.clone(),
+            "services" => services
+        }.pretty(4));
+    } else {
+        for error in errors.iter() {
+            println!("Error: {}", error);
+        }
+        for warning in warnings.iter() {
+            println!("Warning: {}", warning);
+        }
+        if ok {
+            println!("{} is valid.", source.path());
+        }
+    }
+    ok
+}
+
+/// Location of the configuration, a single file or a directory of files.
+enum ConfigSource {
+    File(String),
+    Dir(String)
+}
+
+impl ConfigSource {
+    fn read(&self) -> Result<config::Config, AppError> {
+        match self {
+            ConfigSource::File(filename) => config::Config::read_from_file(filename),
+            ConfigSource::Dir(dir) => config::Config::read_from_dir(dir)
+        }
+    }
+
+    fn read_unvalidated(&self) -> Result<config::Config, AppError> {
+        match self {
+            ConfigSource::File(filename) => config::Config::read_unvalidated_from_file(filename),
+            ConfigSource::Dir(dir) => config::Config::read_unvalidated_from_dir(dir)
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            ConfigSource::File(path) | ConfigSource::Dir(path) => path
+        }
+    }
+}
+
+/// Files tried in this order if neither `--config` nor `--config-dir` is given.
+///
+/// `$XDG_CONFIG_HOME` falls back to `~/.config`, as in the XDG base directory specification.
+fn default_config_paths() -> Vec<String> {
+    let mut paths = vec![String::from("./config.json")];
+    let config_home = match (std::env::var("XDG_CONFIG_HOME"), std::env::var("HOME")) {
+        (Ok(dir), _) if !dir.is_empty() => Some(dir),
+        (_, Ok(home)) if !home.is_empty() => Some(format!("{}/.config", home)),
+        _ => None
+    };
+    if let Some(dir) = config_home {
+        paths.push(format!("{}/covid-vacc-poll/config.json", dir));
+    }
+    paths.push(String::from("/etc/covid-vacc-poll/config.json"));
+    paths
+}
+
+/// Re-reads the config file on every SIGHUP and hands it to the poller.
+///
+/// An invalid file is logged and the current configuration stays active.
+#[cfg(unix)]
+fn reload_on_sighup(source: ConfigSource, control_tx: mpsc::Sender<Command>) {
+    let mut signals = Signals::new([SIGHUP]).unwrap();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("SIGHUP received. Reloading {}", source.path());
+            match source.read() {
+                Ok(cfg) => if control_tx.send(Command::Reload(Box::new(cfg))).is_err() {
+                    break;
+                },
+                Err(error) => error!("Keeping the current configuration: {}", error)
+            }
+        }
+    });
+}
+
+/// Shuts the poller down cleanly on SIGTERM, e.g. from systemd or a container runtime.
+#[cfg(unix)]
+fn shutdown_on_sigterm(control_tx: mpsc::Sender<Command>) {
+    let mut signals = Signals::new([SIGTERM]).unwrap();
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            info!("SIGTERM received. Shutting down.");
+            let _ = control_tx.send(Command::Shutdown);
+        }
+    });
+}
 
 fn main() {
     let args = clap::App::new("COVID Vaccination Poll App")
@@ -28,36 +305,149 @@ fn main() {
             .short("c")
             .long("config")
             .takes_value(true)
-            .help("Configuration JSON file"))
+            .help("Configuration JSON file, by default ./config.json, $XDG_CONFIG_HOME/covid-vacc-poll/config.json or /etc/covid-vacc-poll/config.json"))
+        .arg(clap::Arg::with_name("config-dir")
+            .long("config-dir")
+            .takes_value(true)
+            .value_name("DIR")
+            .conflicts_with("config")
+            .help("Directory of configuration JSON files, which are merged"))
         .arg(clap::Arg::with_name("verbose")
             .short("v")
             .long("verbose")
             .takes_value(false)
             .help("Enable verbose output"))
+        .arg(clap::Arg::with_name("log-level")
+            .long("log-level")
+            .takes_value(true)
+            .value_name("LEVELS")
+            .help("Log level with optional overrides per module, e.g. warn,covid_vacc_poll::service=debug"))
+        .arg(clap::Arg::with_name("max-runtime")
+            .long("max-runtime")
+            .takes_value(true)
+            .value_name("SECS")
+            .help("Shut down automatically after the given number of seconds"))
+        .arg(clap::Arg::with_name("once")
+            .long("once")
+            .takes_value(false)
+            .help("Poll every service once and exit"))
+        .arg(clap::Arg::with_name("self-check")
+            .long("self-check")
+            .takes_value(false)
+            .help("Test every service and notification once at startup"))
+        .subcommand(clap::SubCommand::with_name("generate-config")
+            .about("Prints an example configuration using every provider"))
+        .subcommand(clap::SubCommand::with_name("list-services")
+            .about("Prints the configured services and their notifications"))
+        .subcommand(clap::SubCommand::with_name("validate")
+            .about("Checks the configuration and lists all problems, exits with 1 if it is invalid")
+            .arg(clap::Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Output format, json is meant for scripts")))
         .get_matches();
 
-    SimpleLogger::new().with_level(if args.is_present("verbose") {
+    if args.subcommand_matches("generate-config").is_some() {
+        println!("{}", config::sample_config().pretty(4));
+        return;
+    }
+
+    let source = match (args.value_of("config"), args.value_of("config-dir")) {
+        (Some(filename), _) => ConfigSource::File(String::from(filename)),
+        (None, Some(dir)) => ConfigSource::Dir(String::from(dir)),
+        (None, None) => {
+            let paths = default_config_paths();
+            match paths.iter().find(|path| Path::new(path).is_file()) {
+                Some(path) => ConfigSource::File(path.clone()),
+                None => {
+                    eprintln!("No configuration found. Pass --config or --config-dir, or create one of: {}", paths.join(", "));
+                    process::exit(1);
+                }
+            }
+        }
+    };
+    if let Some(validate_args) = args.subcommand_matches("validate") {
+        let ok = validate(&source, validate_args.value_of("format") == Some("json"));
+        process::exit(if ok { 0 } else { 1 });
+    }
+
+    let mut cfg = match source.read() {
+        Ok(cfg) => cfg,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+
+    if args.subcommand_matches("list-services").is_some() {
+        list_services(&cfg);
+        return;
+    }
+
+    let level = if args.is_present("verbose") {
         LevelFilter::Info
     } else {
         LevelFilter::Warn
-    }).init().unwrap();
+    };
+    let levels = match LogLevels::parse(args.value_of("log-level").unwrap_or(""), level) {
+        Ok(levels) => levels,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            process::exit(1);
+        }
+    };
+    // The handle has to be kept alive to keep writing the log file
+    let _logger = match &cfg.log_file {
+        Some(log_file) => match init_file_logger(&levels, log_file) {
+            Ok(logger) => Some(logger),
+            Err(error) => {
+                eprintln!("Log file {} could not be opened: {}", log_file.path, error);
+                process::exit(1);
+            }
+        },
+        None => {
+            let mut logger = SimpleLogger::new().with_level(levels.default);
+            for (module, level) in levels.modules.iter() {
+                logger = logger.with_module_level(module, *level);
+            }
+            logger.init().unwrap();
+            None
+        }
+    };
+    if let Some(max_runtime) = args.value_of("max-runtime") {
+        cfg.max_runtime_secs = match max_runtime.parse() {
+            Ok(secs) => Some(secs),
+            Err(_) => {
+                eprintln!("--max-runtime must be a number of seconds, got \"{}\"", max_runtime);
+                process::exit(1);
+            }
+        };
+    }
 
-    let filename = args.value_of("config").unwrap();
-    let cfg = config::Config::read_from_file(filename).unwrap();
+    if args.is_present("self-check") {
+        cfg.self_check = true;
+    }
 
-    let notifs = notification::NotificatorCollection::from(&cfg);
-    let admin_notifs = AdminNotifications::new(notifs.subcollection(&cfg.admin_notifications));
-    let services = service::ServiceCollection::from(&cfg, &notifs, &admin_notifs);
+    let lifecycle = match &cfg.restart_debounce {
+        Some(settings) => lifecycle_notices(settings),
+        None => LifecycleNotices::default()
+    };
 
-    admin_notifs.get_tx().send("App", "COVID Vaccination Poll App Started");
-
-    let service_killer = services.get_killers();
+    let (control_tx, control_rx) = mpsc::channel();
+    let shutdown_tx = control_tx.clone();
     ctrlc::set_handler(move || {
-        service_killer.kill_all();
+        let _ = shutdown_tx.send(Command::Shutdown);
     }).unwrap();
-    services.join_all();
-    admin_notifs.get_tx().send("App", "COVID Vaccination Poll App Terminated");
+    #[cfg(unix)]
+    shutdown_on_sigterm(control_tx.clone());
 
-    admin_notifs.get_killer().kill();
-    admin_notifs.join().unwrap();
+    if args.is_present("once") {
+        covid_vacc_poll::run_once(cfg, lifecycle, control_rx);
+    } else {
+        #[cfg(unix)]
+        reload_on_sighup(source, control_tx);
+        covid_vacc_poll::run(cfg, lifecycle, control_rx);
+    }
 }
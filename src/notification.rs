@@ -6,171 +6,724 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::{error::Error, thread};
-use std::collections::HashMap;
+use std::thread;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use log::error;
+use log::{info, warn, error};
 
 use gotify::Gotify;
+use affix::Affix;
+use limit::LengthLimit;
+use retry::Retry;
+use filter::SeverityFilter;
+use force::ForceSeverity;
+use rate_limit::RateLimited;
+use digest::Digest;
+use failover::Failover;
+use slack::Slack;
+use mqtt::Mqtt;
+use twilio::Twilio;
+use file::FileLog;
+use apprise::Apprise;
+use syslog::Syslog;
+use mastodon::Mastodon;
+use teams::Teams;
+use pushbullet::Pushbullet;
+use email::Email;
+#[cfg(feature = "desktop")]
+use desktop::Desktop;
+#[cfg(feature = "qr")]
+use qr::QrCodes;
 
-use crate::config::{Config, NotificationSettings};
-use std::sync::{mpsc, Arc, Mutex};
+use crate::config::{Config, AdminNotificationSettings, NotificationSettings, NotificationProviderSettings};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
-use crate::error::GenericError;
+use crate::error::AppError;
+use crate::http_client::ClientPool;
+use crate::shutdown::{DoneSignal, ShutdownRequest, wait_for};
 
+mod format;
+mod affix;
+mod limit;
 mod gotify;
+mod retry;
+mod filter;
+mod force;
+mod rate_limit;
+mod reconnect;
+mod digest;
+mod failover;
+mod slack;
+mod mqtt;
+mod twilio;
+mod file;
+mod apprise;
+mod syslog;
+mod mastodon;
+mod teams;
+mod pushbullet;
+mod email;
+#[cfg(feature = "desktop")]
+mod desktop;
+#[cfg(feature = "qr")]
+mod qr;
+#[cfg(test)]
+pub(crate) mod recording;
+
+pub use format::MessageFormat;
+
+/// Lowest priority of an urgent message
+pub const URGENT_PRIORITY: u8 = 5;
+/// Highest priority of a message, as in Gotify
+pub const MAX_PRIORITY: u8 = 10;
+
+/// Importance of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Normal,
+    Urgent
+}
+
+impl Severity {
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "normal" => Some(Severity::Normal),
+            "urgent" => Some(Severity::Urgent),
+            _ => None
+        }
+    }
+
+    /// Nearest severity of a priority from 0 to 10, for notificators without numeric priorities.
+    pub fn from_priority(priority: u8) -> Severity {
+        if priority >= URGENT_PRIORITY {
+            Severity::Urgent
+        } else {
+            Severity::Normal
+        }
+    }
+}
+
+/// A message to the users or the admins.
+///
+/// The `body` is markdown, which is rendered to the format of each notificator
+/// before it is sent. Notificators may present the optional fields in their own way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub severity: Severity,
+    /// Page to open from the notification, e.g. the booking page
+    pub url: Option<String>,
+    /// Names of the newly freed calendars
+    pub added: Vec<String>,
+    /// Names of the calendars which are no longer free
+    pub removed: Vec<String>,
+    /// Labels of the service, e.g. its region, for filtering on the receiving side
+    pub tags: Vec<String>,
+    /// Sent with the highest priority of the provider, e.g. for a preferred vaccine
+    pub important: bool,
+    /// Booking pages of the newly freed calendars, if the provider knows them
+    pub booking_urls: Vec<String>,
+    /// Same for every attempt to send the message, lets providers drop duplicates, set by the retries
+    pub idempotency_key: Option<String>,
+    /// Finer importance from 0 to 10 within the severity, e.g. rising with the number of freed calendars
+    pub priority: Option<u8>
+}
+
+impl Notification {
+    pub fn new(title: &str, body: &str, severity: Severity) -> Notification {
+        Notification{
+            title: String::from(title),
+            body: String::from(body),
+            severity,
+            url: None,
+            added: Vec::new(),
+            removed: Vec::new(),
+            tags: Vec::new(),
+            important: false,
+            booking_urls: Vec::new(),
+            idempotency_key: None,
+            priority: None
+        }
+    }
+
+    pub fn with_url(mut self, url: Option<String>) -> Notification {
+        self.url = url;
+        self
+    }
+
+    /// Sets the priority, capped at 10, and the severity to its nearest one.
+    pub fn with_priority(mut self, priority: u8) -> Notification {
+        let priority = priority.min(MAX_PRIORITY);
+        self.priority = Some(priority);
+        self.severity = Severity::from_priority(priority);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Notification {
+        self.tags = tags;
+        self
+    }
+
+    /// Tags as hashtags like `#Berlin_Mitte`, which end at the first other character than a letter, digit or `_`.
+    pub fn hashtags(&self) -> String {
+        self.tags.iter()
+            .map(|tag| format!("#{}", tag.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect::<String>()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
 
 pub trait Notificator: Debug + Send + Sync {
-    fn send_normal(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>>;
-    fn send_urgent(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>>;
+    fn send(&self, notification: &Notification) -> Result<(), AppError>;
+
+    /// Markup the message body is rendered to before it is passed to this notificator.
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Markdown
+    }
+
+    /// Longest message body in characters this notificator accepts.
+    ///
+    /// Longer bodies are shortened when they are rendered, see [`format::render_limited`].
+    fn max_length(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether images embedded as data URIs in a markdown or HTML body are shown.
+    ///
+    /// Most chat and mail clients block such images, so QR codes are only appended if this holds.
+    fn shows_images(&self) -> bool {
+        false
+    }
+}
+
+/// Lets a notificator be shared, e.g. between its wrappers and the self-check.
+impl Notificator for Arc<dyn Notificator> {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.as_ref().send(notification)
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.as_ref().format()
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        self.as_ref().max_length()
+    }
+
+    fn shows_images(&self) -> bool {
+        self.as_ref().shows_images()
+    }
+}
+
+#[derive(Debug)]
+struct ConfiguredNotificator {
+    settings: NotificationSettings,
+    notificator: Arc<Mutex<dyn Notificator>>,
+    /// The provider without the filters, retries and digest, for the self-check
+    provider: Arc<dyn Notificator>
 }
 
 #[derive(Debug)]
 pub struct NotificatorCollection {
-    notificators: HashMap<String, Arc<Mutex<dyn Notificator>>>
+    notificators: HashMap<String, ConfiguredNotificator>,
+    clients: ClientPool
 }
 
 impl NotificatorCollection {
-    fn new() -> NotificatorCollection {
+    fn new(clients: &ClientPool) -> NotificatorCollection {
         NotificatorCollection{
-            notificators: HashMap::new()
+            notificators: HashMap::new(),
+            clients: clients.clone()
         }
     }
 
-    fn add(&mut self, name: &String, provider: Arc<Mutex<dyn Notificator>>) {
-        self.notificators.insert(name.clone(), provider);
+    fn add(&mut self, name: &str, settings: &NotificationSettings) {
+        let (notificator, provider) = Self::build(settings, &self.clients);
+        self.notificators.insert(String::from(name), ConfiguredNotificator{
+            settings: settings.clone(),
+            notificator,
+            provider
+        });
     }
 
-    pub fn from(config: &Config) -> NotificatorCollection {
-        let mut coll = NotificatorCollection::new();
+    /// Returns the notificator with all wrappers and the provider without the ones which may hold back a message.
+    fn build(settings: &NotificationSettings, clients: &ClientPool) -> (Arc<Mutex<dyn Notificator>>, Arc<dyn Notificator>) {
+        let provider: Box<dyn Notificator> = match &settings.provider {
+            NotificationProviderSettings::Gotify(s) => Box::new(Gotify::from(s, clients)),
+            NotificationProviderSettings::Slack(s) => Box::new(Slack::from(s, clients)),
+            NotificationProviderSettings::Mqtt(s) => Box::new(Mqtt::from(s)),
+            NotificationProviderSettings::Twilio(s) => Box::new(Twilio::from(s, clients)),
+            NotificationProviderSettings::File(s) => Box::new(FileLog::from(s)),
+            NotificationProviderSettings::Apprise(s) => Box::new(Apprise::from(s, clients)),
+            NotificationProviderSettings::Syslog(s) => Box::new(Syslog::from(s)),
+            NotificationProviderSettings::Mastodon(s) => Box::new(Mastodon::from(s, clients)),
+            NotificationProviderSettings::Teams(s) => Box::new(Teams::from(s, clients)),
+            NotificationProviderSettings::Pushbullet(s) => Box::new(Pushbullet::from(s, clients)),
+            NotificationProviderSettings::Failover(s) => Box::new(Failover::new(s.notifications.iter()
+                .filter(|inner| inner.enabled)
+                .map(|inner| Self::build(inner, clients).0)
+                .collect())),
+            #[cfg(feature = "desktop")]
+            NotificationProviderSettings::Desktop(s) => Box::new(Desktop::from(s)),
+            NotificationProviderSettings::Email(s) => Box::new(Email::from(s))
+        };
+        let provider: Box<dyn Notificator> = match settings.max_length {
+            Some(max_length) => Box::new(LengthLimit::new(provider, max_length as usize)),
+            None => provider
+        };
+        // Outside of the length limit, so the codes are left out if they exceed it
+        #[cfg(feature = "qr")]
+        let provider: Box<dyn Notificator> = if settings.qr_code && provider.shows_images() {
+            Box::new(QrCodes::new(provider))
+        } else {
+            if settings.qr_code {
+                warn!("QR codes are left out, as the notification provider cannot show images");
+            }
+            provider
+        };
+        let provider: Box<dyn Notificator> = if settings.prefix.is_empty() && settings.suffix.is_empty() {
+            provider
+        } else {
+            Box::new(Affix::new(provider, &settings.prefix, &settings.suffix))
+        };
+        let provider: Arc<dyn Notificator> = Arc::from(provider);
+        let notif = Retry::new(Box::new(provider.clone()), settings.max_retries, Duration::from_secs(settings.retry_delay_secs as u64));
+        let notif = RateLimited::new(notif, settings.max_per_minute);
+        // Filtered messages do not show up in the digest either
+        let notif: Box<dyn Notificator> = match &settings.digest {
+            Some(digest) => Box::new(Digest::new(Box::new(notif), digest)),
+            None => Box::new(notif)
+        };
+        let notif = SeverityFilter::new(notif, settings.min_severity);
+        // Forcing happens first, so the filter sees the forced severity
+        let notif: Arc<Mutex<dyn Notificator>> = match settings.force_severity {
+            Some(severity) => Arc::new(Mutex::new(ForceSeverity::new(Box::new(notif), severity))),
+            None => Arc::new(Mutex::new(notif))
+        };
+        (notif, provider)
+    }
+
+    /// HTTP based notificators use the shared clients of `clients`.
+    pub fn from(config: &Config, clients: &ClientPool) -> NotificatorCollection {
+        let mut coll = NotificatorCollection::new(clients);
         for (name, settings) in config.notifications.iter() {
-            let notif = match settings {
-                NotificationSettings::Gotify(s) => Arc::new(Mutex::new(Gotify::from(s))),
-                NotificationSettings::Email(_) => Arc::new(Mutex::new(Gotify::new(&String::from(""), &String::from(""))))
-            };
-            coll.add(name, notif);
+            if settings.enabled {
+                coll.add(name, settings);
+            } else {
+                info!("Notification {} is disabled", name);
+            }
         }
         coll
     }
 
+    /// Rebuilds the notificators whose settings changed in `config`.
+    ///
+    /// Unchanged notificators are kept. Returns the names of all notificators
+    /// which were added, changed, disabled or removed.
+    pub fn reload(&mut self, config: &Config) -> HashSet<String> {
+        let enabled = |name: &String| match config.notifications.get(name) {
+            Some(settings) => settings.enabled,
+            None => false
+        };
+        let mut changed: HashSet<String> = self.notificators.keys()
+            .filter(|name| !enabled(name))
+            .cloned()
+            .collect();
+        self.notificators.retain(|name, _| !changed.contains(name));
+        for (name, settings) in config.notifications.iter().filter(|(_, settings)| settings.enabled) {
+            let unchanged = match self.notificators.get(name) {
+                Some(current) => current.settings == *settings,
+                None => false
+            };
+            if !unchanged {
+                self.add(name, settings);
+                changed.insert(name.clone());
+            }
+        }
+        changed
+    }
+
+    /// Sends an urgent test message to the provider of every notificator.
+    ///
+    /// The severity filter, digest and rate limit are bypassed, so the
+    /// provider is always contacted and nothing is left in a digest.
+    /// Failures are logged and reported to `admin_notif`. Returns `true` if
+    /// all test messages were delivered.
+    pub fn self_check(&self, admin_notif: &AdminNotificationsSender) -> bool {
+        let mut ok = true;
+        for (name, configured) in self.notificators.iter() {
+            let provider = &configured.provider;
+            let message = format::render("Test message. Notifications are working.", provider.format());
+            match provider.send(&Notification::new("COVID Vaccination Poll - Self-Check", message.as_str(), Severity::Urgent)) {
+                Ok(_) => info!("Self-check of notification {} passed", name),
+                Err(error) => {
+                    ok = false;
+                    let msg = format!("Self-check of notification {} failed: {}", name, error);
+                    error!("{}", msg);
+                    admin_notif.send("Self-Check", msg.as_str());
+                }
+            }
+        }
+        ok
+    }
+
     // pub fn get(&self, name: &String) -> Arc<Mutex<dyn Notificator>> {
     //     self.notificators[name].clone()
     // }
 
+    /// Collects the named notificators, skipping disabled ones.
+    ///
+    /// The notificators are sent to in the order of `names`.
     pub fn subcollection(&self, names: &Vec<String>) -> NotificatorSubCollection {
-        let mut arr: Vec<Arc<Mutex<dyn Notificator>>> = Vec::new();
+        let mut arr: Vec<(String, Arc<Mutex<dyn Notificator>>)> = Vec::new();
         for name in names {
-            arr.push(self.notificators[name].clone());
+            if let Some(configured) = self.notificators.get(name) {
+                arr.push((name.clone(), configured.notificator.clone()));
+            }
         }
         NotificatorSubCollection{
-            notificators: arr
+            notificators: arr,
+            fail_fast: false
         }
     }
 }
 
+/// A list of notificators a message is sent to.
+///
+/// By default every notificator gets the message, even if some of them fail
+/// (fail-soft). Failures are logged and sending only fails if no notificator
+/// succeeded. In fail-fast mode, sending stops at the first failure.
 #[derive(Debug)]
 pub struct NotificatorSubCollection {
-    notificators: Vec<Arc<Mutex<dyn Notificator>>>
+    notificators: Vec<(String, Arc<Mutex<dyn Notificator>>)>,
+    fail_fast: bool
+}
+
+impl NotificatorSubCollection {
+    pub fn fail_fast(mut self, fail_fast: bool) -> NotificatorSubCollection {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Locks a notificator, recovering it if a previous send panicked.
+    fn lock(notif: &Arc<Mutex<dyn Notificator>>) -> MutexGuard<'_, dyn Notificator + 'static> {
+        match notif.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                warn!("Notificator panicked in a previous send. Continuing with its last state.");
+                notif.clear_poison();
+                poisoned.into_inner()
+            }
+        }
+    }
 }
 
 impl Notificator for NotificatorSubCollection {
-    fn send_normal(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>> {
-        for notif in self.notificators.iter() {
-            match notif.lock() {
-                Ok(l) => l,
-                Err(err) => return Err(Box::new(GenericError::new(err.to_string().as_str())))
-            }.send_normal(title, message)?;
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mut errors: Vec<String> = Vec::new();
+        for (name, notif) in self.notificators.iter() {
+            let locked = Self::lock(notif);
+            let result = locked.send(&Notification{
+                body: format::render_limited(&notification.body, locked.format(), locked.max_length()),
+                ..notification.clone()
+            });
+            if let Err(error) = result {
+                if self.fail_fast {
+                    return Err(error);
+                }
+                errors.push(format!("{}: {}", name, error));
+            }
+        }
+        if !errors.is_empty() && errors.len() == self.notificators.len() {
+            return Err(AppError::Notification(format!("All notifications failed. {}", errors.join("; "))));
+        }
+        // Partial failures are not returned, so they are only logged
+        for error in errors {
+            error!("Notification {} failed", error);
         }
         Ok(())
     }
+}
 
-    fn send_urgent(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>> {
-        for notif in self.notificators.iter() {
-            match notif.lock() {
-                Ok(l) => l,
-                Err(err) => return Err(Box::new(GenericError::new(err.to_string().as_str())))
-            }.send_urgent(title, message)?;
+/// Kind of an admin message, used to route it to the right notificators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminMessageKind {
+    /// Failed polls and notifications
+    Error,
+    /// Start, stop and reload of the app
+    Lifecycle
+}
+
+/// The notificators admin messages are routed to.
+#[derive(Debug)]
+pub struct AdminRecipients {
+    pub errors: NotificatorSubCollection,
+    pub lifecycle: NotificatorSubCollection
+}
+
+impl AdminRecipients {
+    pub fn from(notificators: &NotificatorCollection, settings: &AdminNotificationSettings) -> AdminRecipients {
+        AdminRecipients{
+            errors: notificators.subcollection(&settings.errors),
+            lifecycle: notificators.subcollection(&settings.lifecycle)
+        }
+    }
+
+    fn send(&self, kind: AdminMessageKind, title: &str, msg: &str) {
+        let notificators = match kind {
+            AdminMessageKind::Error => &self.errors,
+            AdminMessageKind::Lifecycle => &self.lifecycle
+        };
+        if let Err(error) = notificators.send(&Notification::new(title, msg, Severity::Normal)) {
+            error!("{}", error.to_string().as_str());
         }
-        Ok(())
     }
 }
 
+/// Admin messages which may wait to be sent, further ones are dropped
+const ADMIN_QUEUE_SIZE: usize = 100;
+
 pub struct AdminNotifications {
     thrd: thread::JoinHandle<()>,
     kill_tx: mpsc::Sender<bool>,
-    msg_tx: mpsc::Sender<String>
+    msg_tx: mpsc::SyncSender<(AdminMessageKind, String)>,
+    dropped: Arc<AtomicUsize>,
+    recipients_tx: mpsc::Sender<AdminRecipients>,
+    done_rx: mpsc::Receiver<()>,
+    shutdown: ShutdownRequest
 }
 
 impl AdminNotifications {
-    pub fn new(notificators: NotificatorSubCollection) -> AdminNotifications {
-        let (msg_tx, msg_rx): (mpsc::Sender<String>, mpsc::Receiver<String>) = mpsc::channel();
+    /// Every admin message is sent with `title`.
+    pub fn new(recipients: AdminRecipients, title: &str) -> AdminNotifications {
+        let (msg_tx, msg_rx) = mpsc::sync_channel::<(AdminMessageKind, String)>(ADMIN_QUEUE_SIZE);
         let (kill_tx, kill_rx) = mpsc::channel();
+        let (recipients_tx, recipients_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let thread_dropped = dropped.clone();
+        let title = String::from(title);
         let thrd = thread::spawn(move || {
+            let _done = DoneSignal::new(done_tx);
+            let mut recipients = recipients;
             let mut running = true;
             while running {
-                thread::sleep(Duration::from_secs(1));
-                match msg_rx.try_recv() {
-                    Ok(msg) => match notificators.send_normal("COVID Vaccination Poll - Admin", msg.as_str()) {
-                        Ok(_) => (),
-                        Err(error) => error!("{}", error.to_string().as_str())
-                    },
-                    Err(_) => ()
+                if let Ok(replacement) = recipients_rx.try_recv() {
+                    recipients = replacement;
+                }
+                match msg_rx.recv_timeout(Duration::from_secs(1)) {
+                    Ok((kind, msg)) => recipients.send(kind, &title, msg.as_str()),
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => { running = false; }
+                }
+                let lost = thread_dropped.swap(0, Ordering::Relaxed);
+                if lost > 0 {
+                    let msg = format!("App: {} admin messages were dropped, because too many were queued", lost);
+                    recipients.send(AdminMessageKind::Error, &title, msg.as_str());
                 }
-                match kill_rx.try_recv() {
-                    Ok(_) => { running = false; },
-                    Err(_) => ()
+                if kill_rx.try_recv().is_ok() {
+                    // Flush everything queued before the kill signal
+                    while let Ok((kind, msg)) = msg_rx.try_recv() {
+                        recipients.send(kind, &title, msg.as_str());
+                    }
+                    running = false;
                 }
             }
         });
         AdminNotifications{
             thrd,
             kill_tx,
-            msg_tx
+            msg_tx,
+            dropped,
+            recipients_tx,
+            done_rx,
+            shutdown: ShutdownRequest::new()
         }
     }
 
+    /// Replaces the notificators the admin messages are sent to.
+    ///
+    /// Messages which are already queued may still go to the old notificators.
+    pub fn set_recipients(&self, recipients: AdminRecipients) {
+        // The thread only stops after a kill, so the receiver is still alive
+        let _ = self.recipients_tx.send(recipients);
+    }
+
     pub fn get_killer(&self) -> AdminNotificationsKiller {
         AdminNotificationsKiller{
-            kill_tx: self.kill_tx.clone()
+            kill_tx: self.kill_tx.clone(),
+            shutdown: self.shutdown.clone()
         }
     }
 
-    pub fn join(self) -> thread::Result<()> {
+    /// Waits for the pending messages to be flushed, at most for `timeout` after the kill.
+    pub fn join(self, timeout: Duration) -> thread::Result<()> {
+        if !wait_for(&self.done_rx, 1, &self.shutdown, timeout) {
+            warn!("Admin notifications were not flushed within {} s.", timeout.as_secs());
+            return Ok(());
+        }
         self.thrd.join()
     }
 
     pub fn get_tx(&self) -> AdminNotificationsSender {
         AdminNotificationsSender {
-            msg_tx: self.msg_tx.clone()
+            msg_tx: self.msg_tx.clone(),
+            dropped: self.dropped.clone()
         }
     }
 }
 
 pub struct AdminNotificationsKiller {
-    kill_tx: mpsc::Sender<bool>
+    kill_tx: mpsc::Sender<bool>,
+    shutdown: ShutdownRequest
 }
 
 impl AdminNotificationsKiller {
     pub fn kill(&self) {
-        self.kill_tx.send(true).unwrap();
+        self.shutdown.request();
+        // The thread may already be gone, then there is nothing to stop
+        let _ = self.kill_tx.send(true);
     }
 }
 
 pub struct AdminNotificationsSender {
-    msg_tx: mpsc::Sender<String>
+    msg_tx: mpsc::SyncSender<(AdminMessageKind, String)>,
+    /// Messages dropped since the admin thread last reported them
+    dropped: Arc<AtomicUsize>
 }
 
 impl AdminNotificationsSender {
+    /// Reports an error to the admins.
     pub fn send(&self, title: &str, message: &str) {
+        self.send_kind(AdminMessageKind::Error, title, message);
+    }
+
+    /// Informs the admins about the start, stop or reload of the app.
+    pub fn send_lifecycle(&self, title: &str, message: &str) {
+        self.send_kind(AdminMessageKind::Lifecycle, title, message);
+    }
+
+    fn send_kind(&self, kind: AdminMessageKind, title: &str, message: &str) {
         let msg = format!("{}: {}", title, message);
-        self.msg_tx.send(msg).unwrap();
+        // Never blocks, so a storm of errors cannot stall the services
+        match self.msg_tx.try_send((kind, msg)) {
+            Ok(()) => (),
+            Err(mpsc::TrySendError::Full(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Admin notification queue is full. Dropping messages.");
+                }
+            },
+            Err(mpsc::TrySendError::Disconnected((_, msg))) => {
+                error!("Admin notifications are stopped. Dropped message: {}", msg);
+            }
+        }
     }
 }
 
 impl Clone for AdminNotificationsSender {
     fn clone(&self) -> Self {
         AdminNotificationsSender {
-            msg_tx: self.msg_tx.clone()
+            msg_tx: self.msg_tx.clone(),
+            dropped: self.dropped.clone()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use recording::{RecordingNotificator, subcollection};
+    use json::{object, array};
+
+    #[test]
+    fn failed_notificator_does_not_block_the_others() {
+        let failing = RecordingNotificator::failing();
+        let working = RecordingNotificator::new();
+        let notification = Notification::new("Title", "Body", Severity::Urgent);
+
+        assert!(subcollection(&[failing.clone(), working.clone()]).send(&notification).is_ok());
+        assert_eq!(working.lock().unwrap().sent().len(), 1);
+
+        let error = subcollection(&[failing.clone(), failing.clone()]).send(&notification).unwrap_err();
+        assert_eq!(error.to_string(), "Notification error: All notifications failed. n0: Notification error: down; n1: Notification error: down");
+
+        assert!(subcollection(&[failing, working.clone()]).fail_fast(true).send(&notification).is_err());
+        assert_eq!(working.lock().unwrap().sent().len(), 1);
+    }
+
+    #[test]
+    fn self_check_bypasses_filter_and_digest() {
+        let dir = std::env::temp_dir().join(format!("covid-vacc-poll-self-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("messages.log");
+        let config = dir.join("config.json");
+        std::fs::write(&config, object!{
+            "admin_notifications" => array![],
+            "services" => array![],
+            "notifications" => object!{
+                "file" => object!{
+                    "provider" => "file",
+                    "min_severity" => "urgent",
+                    "force_severity" => "normal",
+                    "digest" => object!{"cron" => "0 0 0 1 1 * 2099"},
+                    "settings" => object!{"path" => log.to_str().unwrap()}
+                }
+            }
+        }.dump()).unwrap();
+        let config = Config::read_from_file(config.to_str().unwrap()).unwrap();
+        let notifs = NotificatorCollection::from(&config, &ClientPool::new(Duration::from_secs(10)));
+        let admin = AdminNotifications::new(AdminRecipients{errors: subcollection(&[]), lifecycle: subcollection(&[])}, "Admin");
+
+        assert!(notifs.self_check(&admin.get_tx()));
+        assert!(std::fs::read_to_string(&log).unwrap().contains("Test message. Notifications are working."));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "qr")]
+    #[test]
+    fn qr_codes_respect_the_configured_length_limit() {
+        let dir = std::env::temp_dir().join(format!("covid-vacc-poll-qr-limit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let notification = |max_length: u32| object!{
+            "provider" => "file",
+            "qr_code" => true,
+            "max_length" => max_length,
+            "settings" => object!{"path" => dir.join(format!("{}.log", max_length)).to_str().unwrap(), "format" => "markdown"}
+        };
+        let config = dir.join("config.json");
+        std::fs::write(&config, object!{
+            "admin_notifications" => array![],
+            "services" => array![],
+            "notifications" => object!{
+                "short" => notification(200),
+                "long" => notification(100000)
+            }
+        }.dump()).unwrap();
+        let config = Config::read_from_file(config.to_str().unwrap()).unwrap();
+        let notifs = NotificatorCollection::from(&config, &ClientPool::new(Duration::from_secs(10)));
+        let message = Notification::new("Title", "* Calendar 1", Severity::Urgent)
+            .with_url(Some(String::from("https://example.com/booking/1")));
+
+        notifs.subcollection(&vec![String::from("short"), String::from("long")]).send(&message).unwrap();
+        assert!(!std::fs::read_to_string(dir.join("200.log")).unwrap().contains("data:image/svg+xml"));
+        assert!(std::fs::read_to_string(dir.join("100000.log")).unwrap().contains("data:image/svg+xml"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn full_or_closed_admin_queue_drops_messages() {
+        let (msg_tx, msg_rx) = mpsc::sync_channel(1);
+        let sender = AdminNotificationsSender{ msg_tx, dropped: Arc::new(AtomicUsize::new(0)) };
+
+        sender.send("Test", "first");
+        sender.send("Test", "second");
+        sender.send("Test", "third");
+        assert_eq!(sender.dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(msg_rx.try_recv().unwrap().1, "Test: first");
+
+        drop(msg_rx);
+        sender.send("Test", "closed");
+    }
+}
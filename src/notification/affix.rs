@@ -0,0 +1,58 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification};
+
+/// Puts a prefix in front of every title and appends a suffix to every message.
+///
+/// Helps to tell the messages of the poller apart on channels shared with other sources.
+#[derive(Debug)]
+pub struct Affix {
+    inner: Box<dyn Notificator>,
+    prefix: String,
+    suffix: String
+}
+
+impl Affix {
+    pub fn new(inner: Box<dyn Notificator>, prefix: &str, suffix: &str) -> Affix {
+        Affix{
+            inner,
+            prefix: String::from(prefix),
+            suffix: String::from(suffix)
+        }
+    }
+}
+
+impl Notificator for Affix {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mut wrapped = notification.clone();
+        if !self.prefix.is_empty() {
+            wrapped.title = format!("{} {}", self.prefix, notification.title);
+        }
+        if !self.suffix.is_empty() {
+            wrapped.body = format!("{}\n{}", notification.body.trim_end(), self.suffix);
+        }
+        self.inner.send(&wrapped)
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+    /// Leaves room for the suffix, which is appended after shortening.
+    fn max_length(&self) -> Option<usize> {
+        if self.suffix.is_empty() {
+            return self.inner.max_length();
+        }
+        self.inner.max_length().map(|max_length| max_length.saturating_sub(self.suffix.chars().count() + 1))
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
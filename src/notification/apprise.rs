@@ -0,0 +1,71 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, Notification, Severity};
+use async_std::task;
+use crate::config::{AppriseSettings, AppriseTarget};
+use crate::http_client::ClientPool;
+use json::object;
+
+/// Sends notifications through an Apprise API server, which forwards them
+/// to any of the services supported by Apprise.
+#[derive(Debug)]
+pub struct Apprise {
+    notify_url: String,
+    urls: Option<String>,
+    urgent_type: String,
+    client: reqwest::Client
+}
+
+impl Apprise {
+    pub fn from(settings: &AppriseSettings, clients: &ClientPool) -> Apprise {
+        let (notify_url, urls) = match &settings.target {
+            AppriseTarget::ConfigKey(key) => (format!("{}/notify/{}", settings.server_url, key), None),
+            AppriseTarget::Urls(urls) => (format!("{}/notify/", settings.server_url), Some(urls.join(" ")))
+        };
+        Apprise{
+            notify_url,
+            urls,
+            urgent_type: settings.urgent_type.clone(),
+            client: clients.default_client()
+        }
+    }
+
+    pub async fn send_message(&self, title: &str, message: &str, msg_type: &str) -> Result<(), AppError> {
+        let mut payload = object!{
+            "title" => title,
+            "body" => message,
+            "type" => msg_type,
+            "format" => "markdown"
+        };
+        if let Some(urls) = &self.urls {
+            payload["urls"] = urls.as_str().into();
+        }
+        self.client.post(&self.notify_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.dump())
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub fn send_message_blocking(&self, title: &str, message: &str, msg_type: &str) -> Result<(), AppError> {
+        task::block_on(self.send_message(title, message, msg_type))
+    }
+}
+
+impl Notificator for Apprise {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let msg_type = match notification.severity {
+            Severity::Normal => "info",
+            Severity::Urgent => self.urgent_type.as_str()
+        };
+        self.send_message_blocking(&notification.title, &notification.body, msg_type)
+    }
+}
@@ -0,0 +1,50 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+use crate::config::DesktopSettings;
+use notify_rust::Notification as DesktopNotification;
+
+/// Pops up native notifications on the desktop the poller runs on.
+#[derive(Debug)]
+pub struct Desktop {}
+
+impl Desktop {
+    pub fn from(_settings: &DesktopSettings) -> Desktop {
+        Desktop{}
+    }
+
+    fn show(&self, title: &str, message: &str, urgent: bool) -> Result<(), AppError> {
+        let mut notification = DesktopNotification::new();
+        notification.summary(title).body(message);
+        // Urgency hints are only supported by the freedesktop notification spec
+        #[cfg(all(unix, not(target_os = "macos")))]
+        notification.urgency(if urgent {
+            notify_rust::Urgency::Critical
+        } else {
+            notify_rust::Urgency::Normal
+        });
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        let _ = urgent;
+        match notification.show() {
+            Ok(_) => Ok(()),
+            Err(error) => Err(AppError::Notification(error.to_string()))
+        }
+    }
+}
+
+impl Notificator for Desktop {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.show(&notification.title, &notification.body, notification.severity == Severity::Urgent)
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Plaintext
+    }
+}
@@ -0,0 +1,152 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::config::DigestSettings;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity, format};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use log::{warn, error};
+
+type Pending = Arc<Mutex<Vec<(DateTime<Local>, Notification)>>>;
+
+/// Collects the messages and sends them as one summary at the times of a cron schedule.
+///
+/// Messages arrive as markdown, so they can be combined before being rendered
+/// for the inner notificator. A flush thread waits for the next digest time.
+/// When the wrapper is dropped, e.g. on a reload, the collected messages are
+/// sent right away.
+#[derive(Debug)]
+pub struct Digest {
+    inner: Arc<dyn Notificator>,
+    realtime: bool,
+    pending: Pending,
+    /// Dropping the sender stops the flush thread
+    _stop_tx: mpsc::Sender<()>
+}
+
+impl Digest {
+    pub fn new(inner: Box<dyn Notificator>, settings: &DigestSettings) -> Digest {
+        let inner: Arc<dyn Notificator> = Arc::from(inner);
+        let pending: Pending = Arc::new(Mutex::new(Vec::new()));
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let thread_inner = inner.clone();
+        let thread_pending = pending.clone();
+        let schedule = settings.cron.clone();
+        thread::spawn(move || {
+            loop {
+                let wait = match schedule.upcoming(Local).next() {
+                    Some(next) => (next - Local::now()).to_std().unwrap_or_else(|_| Duration::from_secs(0)),
+                    None => {
+                        warn!("Digest schedule has no upcoming times. Messages are sent when the notification is stopped.");
+                        let _ = stop_rx.recv();
+                        Self::flush(thread_inner.as_ref(), &thread_pending);
+                        return;
+                    }
+                };
+                match stop_rx.recv_timeout(wait) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => Self::flush(thread_inner.as_ref(), &thread_pending),
+                    Ok(_) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        Self::flush(thread_inner.as_ref(), &thread_pending);
+                        return;
+                    }
+                }
+            }
+        });
+        Digest{
+            inner,
+            realtime: settings.realtime,
+            pending,
+            _stop_tx: stop_tx
+        }
+    }
+
+    fn lock(pending: &Pending) -> MutexGuard<'_, Vec<(DateTime<Local>, Notification)>> {
+        match pending.lock() {
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    fn flush(inner: &dyn Notificator, pending: &Pending) {
+        let entries = std::mem::take(&mut *Self::lock(pending));
+        if entries.is_empty() {
+            return;
+        }
+        if let Err(error) = inner.send(&summary(&entries, inner.format(), inner.max_length())) {
+            error!("Digest of {} messages could not be sent: {}", entries.len(), error);
+        }
+    }
+}
+
+/// Combines the collected messages into one, the most severe one sets the severity and priority.
+fn summary(entries: &[(DateTime<Local>, Notification)], format: MessageFormat, max_length: Option<usize>) -> Notification {
+    let mut body = format!("{} messages since {}\n\n", entries.len(), entries[0].0.format("%Y-%m-%d %H:%M"));
+    let mut severity = Severity::Normal;
+    let mut url = None;
+    let mut added: Vec<String> = Vec::new();
+    let mut removed: Vec<String> = Vec::new();
+    let mut booking_urls: Vec<String> = Vec::new();
+    for (time, notification) in entries.iter() {
+        body.push_str(&format!("**{} {}**\n{}\n\n", time.format("%H:%M"), notification.title, notification.body.trim_end()));
+        severity = severity.max(notification.severity);
+        if notification.url.is_some() {
+            url = notification.url.clone();
+        }
+        added.extend(notification.added.iter().filter(|name| !added.contains(name)).cloned().collect::<Vec<_>>());
+        removed.extend(notification.removed.iter().filter(|name| !removed.contains(name)).cloned().collect::<Vec<_>>());
+        booking_urls.extend(notification.booking_urls.iter().filter(|url| !booking_urls.contains(url)).cloned().collect::<Vec<_>>());
+    }
+    let mut digest = Notification::new("COVID Vaccination Poll - Digest", format::render_limited(body.trim_end(), format, max_length).as_str(), severity)
+        .with_url(url);
+    digest.added = added;
+    digest.removed = removed;
+    digest.booking_urls = booking_urls;
+    digest.important = entries.iter().any(|(_, notification)| notification.important);
+    digest.priority = entries.iter().filter_map(|(_, notification)| notification.priority).max();
+    digest
+}
+
+impl Notificator for Digest {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        Self::lock(&self.pending).push((Local::now(), notification.clone()));
+        if self.realtime {
+            let mut rendered = notification.clone();
+            rendered.body = format::render_limited(notification.body.as_str(), self.inner.format(), self.inner.max_length());
+            return self.inner.send(&rendered);
+        }
+        Ok(())
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_combines_the_messages() {
+        let time = Local::now();
+        let mut first = Notification::new("A", "* Center 1", Severity::Normal);
+        first.added = vec![String::from("Center 1")];
+        let second = Notification::new("B", "* Center 2", Severity::Urgent).with_url(Some(String::from("https://example.com")));
+
+        let digest = summary(&[(time, first), (time, second)], MessageFormat::Markdown, None);
+        assert_eq!(digest.severity, Severity::Urgent);
+        assert_eq!(digest.url, Some(String::from("https://example.com")));
+        assert_eq!(digest.added, vec![String::from("Center 1")]);
+        assert!(digest.body.starts_with("2 messages since "));
+        assert!(digest.body.contains(&format!("**{} A**\n* Center 1", time.format("%H:%M"))));
+        assert!(digest.body.ends_with("B**\n* Center 2"));
+    }
+}
@@ -0,0 +1,118 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification};
+use crate::config::EmailSettings;
+use lettre::{Message, SmtpTransport, Transport};
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+
+/// Sends the messages as e-mails through an SMTP server.
+///
+/// The server is reached with STARTTLS or, if disabled, with implicit TLS.
+/// The title of a message is appended to the configured subject.
+pub struct Email {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: String,
+    format: MessageFormat
+}
+
+impl std::fmt::Debug for Email {
+    // The transport holds the password
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Email")
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .field("subject", &self.subject)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl Email {
+    pub fn from(settings: &EmailSettings) -> Email {
+        let builder = if settings.smtp_starttls {
+            SmtpTransport::starttls_relay(&settings.smtp_host)
+        } else {
+            SmtpTransport::relay(&settings.smtp_host)
+        };
+        let transport = builder.expect("SMTP host is validated when loading the config")
+            .port(settings.smtp_port)
+            .credentials(Credentials::new(settings.smtp_user.clone(), settings.smtp_password.clone()))
+            .build();
+        Email{
+            transport,
+            from: settings.from.parse().expect("Sender is validated when loading the config"),
+            to: settings.to.iter()
+                .map(|to| to.parse().expect("Recipients are validated when loading the config"))
+                .collect(),
+            subject: settings.subject.clone(),
+            format: settings.format
+        }
+    }
+
+    fn message(&self, notification: &Notification) -> Result<Message, AppError> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("{}: {}", self.subject, notification.title))
+            .header(match self.format {
+                MessageFormat::Html => ContentType::TEXT_HTML,
+                MessageFormat::Markdown | MessageFormat::Plaintext => ContentType::TEXT_PLAIN
+            });
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        builder.body(notification.body.clone())
+            .map_err(|error| AppError::Notification(format!("E-mail could not be built: {}", error)))
+    }
+}
+
+impl Notificator for Email {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.transport.send(&self.message(notification)?)
+            .map_err(|error| AppError::Notification(format!("E-mail could not be sent: {}", error)))?;
+        Ok(())
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.format
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::Severity;
+
+    #[test]
+    fn html_body_is_sent_as_html() {
+        let settings = EmailSettings{
+            from: String::from("poll@example.com"),
+            to: vec![String::from("me@example.com"), String::from("you@example.com")],
+            subject: String::from("COVID Vaccination Poll"),
+            smtp_host: String::from("mail.example.com"),
+            smtp_port: 587,
+            smtp_user: String::from("user"),
+            smtp_password: String::from("password"),
+            smtp_starttls: true,
+            format: MessageFormat::Html
+        };
+        let email = Email::from(&settings);
+
+        let message = email.message(&Notification::new("Title", "<p>Free</p>", Severity::Urgent)).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("Subject: COVID Vaccination Poll: Title\r\n"));
+        assert!(formatted.contains("To: me@example.com, you@example.com\r\n"));
+        assert!(formatted.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(formatted.ends_with("<p>Free</p>"));
+    }
+}
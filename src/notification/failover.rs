@@ -0,0 +1,82 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, NotificatorSubCollection, format};
+use std::sync::{Arc, Mutex};
+use log::{info, warn};
+
+/// Sends a message through the first of its notificators which works.
+///
+/// The next notificator is only tried if the previous one failed, including
+/// its retries. Messages arrive as markdown and are rendered for each of them.
+#[derive(Debug)]
+pub struct Failover {
+    notificators: Vec<Arc<Mutex<dyn Notificator>>>
+}
+
+impl Failover {
+    pub fn new(notificators: Vec<Arc<Mutex<dyn Notificator>>>) -> Failover {
+        Failover{
+            notificators
+        }
+    }
+}
+
+impl Notificator for Failover {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mut errors: Vec<String> = Vec::new();
+        for (index, notif) in self.notificators.iter().enumerate() {
+            let locked = NotificatorSubCollection::lock(notif);
+            let result = locked.send(&Notification{
+                body: format::render_limited(&notification.body, locked.format(), locked.max_length()),
+                ..notification.clone()
+            });
+            match result {
+                Ok(_) => {
+                    if index > 0 {
+                        info!("Message was sent by failover notification {}", index + 1);
+                    }
+                    return Ok(());
+                },
+                Err(error) => {
+                    warn!("Failover notification {} failed: {}", index + 1, error);
+                    errors.push(format!("{}: {}", index + 1, error));
+                }
+            }
+        }
+        Err(AppError::Notification(format!("All failover notifications failed. {}", errors.join("; "))))
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::Severity;
+    use crate::notification::recording::RecordingNotificator;
+
+    #[test]
+    fn secondary_is_only_used_if_primary_fails() {
+        let primary = RecordingNotificator::failing();
+        let secondary = RecordingNotificator::new();
+        let unused = RecordingNotificator::new();
+        let failover = Failover::new(vec![primary.clone(), secondary.clone(), unused.clone()]);
+
+        failover.send(&Notification::new("T", "free", Severity::Urgent)).unwrap();
+        assert_eq!(primary.lock().unwrap().sent().len(), 1);
+        assert_eq!(secondary.lock().unwrap().sent(), vec![(Severity::Urgent, String::from("T"), String::from("free"))]);
+        assert!(unused.lock().unwrap().sent().is_empty());
+
+        let error = Failover::new(vec![primary]).send(&Notification::new("T", "free", Severity::Urgent)).unwrap_err();
+        assert_eq!(error.to_string(), "Notification error: All failover notifications failed. 1: Notification error: down");
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::Local;
+use log::warn;
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification};
+use crate::config::FileSettings;
+
+/// Appends every message as a `timestamp\ttitle\tmessage` line to a file.
+///
+/// Line breaks within the message are escaped as `\n`, so each message
+/// occupies exactly one line.
+#[derive(Debug)]
+pub struct FileLog {
+    path: String,
+    format: MessageFormat,
+    file: Mutex<Option<File>>
+}
+
+impl FileLog {
+    pub fn from(settings: &FileSettings) -> FileLog {
+        FileLog{
+            path: settings.path.clone(),
+            format: settings.format,
+            file: Mutex::new(None)
+        }
+    }
+
+    fn open(&self) -> Result<File, AppError> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        Ok(file)
+    }
+
+    fn append(&self, title: &str, message: &str) -> Result<(), AppError> {
+        let line = format!("{}\t{}\t{}\n", Local::now().to_rfc3339(), title, message.trim_end().replace('\n', "\\n"));
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        // The file may have been moved away by log rotation
+        if file.is_some() && !Path::new(&self.path).exists() {
+            *file = None;
+        }
+        if file.is_none() {
+            *file = Some(self.open()?);
+        }
+        if let Some(handle) = file.as_mut() {
+            if let Err(error) = handle.write_all(line.as_bytes()) {
+                warn!("Writing to {} failed: {}. Reopening the file.", self.path, error);
+                let mut handle = self.open()?;
+                handle.write_all(line.as_bytes())?;
+                *file = Some(handle);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Notificator for FileLog {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.append(&notification.title, &notification.body)
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    /// Markdown and HTML viewers show the images of the messages.
+    fn shows_images(&self) -> bool {
+        true
+    }
+}
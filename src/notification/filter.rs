@@ -0,0 +1,46 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+
+/// Drops messages below a minimum severity.
+#[derive(Debug)]
+pub struct SeverityFilter {
+    inner: Box<dyn Notificator>,
+    min_severity: Severity
+}
+
+impl SeverityFilter {
+    pub fn new(inner: Box<dyn Notificator>, min_severity: Severity) -> SeverityFilter {
+        SeverityFilter{
+            inner,
+            min_severity
+        }
+    }
+}
+
+impl Notificator for SeverityFilter {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        if notification.severity < self.min_severity {
+            return Ok(());
+        }
+        self.inner.send(notification)
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.inner.max_length()
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
@@ -0,0 +1,49 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+
+/// Sends every message with a fixed severity, regardless of the event.
+#[derive(Debug)]
+pub struct ForceSeverity {
+    inner: Box<dyn Notificator>,
+    severity: Severity
+}
+
+impl ForceSeverity {
+    pub fn new(inner: Box<dyn Notificator>, severity: Severity) -> ForceSeverity {
+        ForceSeverity{
+            inner,
+            severity
+        }
+    }
+}
+
+impl Notificator for ForceSeverity {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.inner.send(&Notification{
+            severity: self.severity,
+            // A message forced down to normal must not keep the highest priority
+            important: notification.important && self.severity == Severity::Urgent,
+            priority: notification.priority.filter(|priority| Severity::from_priority(*priority) == self.severity),
+            ..notification.clone()
+        })
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.inner.max_length()
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
@@ -0,0 +1,136 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+/// Markup a notificator expects its message body in.
+///
+/// Service providers always produce markdown. It is converted with [`render`]
+/// before being handed to the notificator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    Markdown,
+    Html,
+    Plaintext
+}
+
+impl MessageFormat {
+    pub fn from_name(name: &str) -> Option<MessageFormat> {
+        match name {
+            "markdown" => Some(MessageFormat::Markdown),
+            "html" => Some(MessageFormat::Html),
+            "plaintext" => Some(MessageFormat::Plaintext),
+            _ => None
+        }
+    }
+}
+
+pub fn render(markdown: &str, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::Markdown => String::from(markdown),
+        MessageFormat::Html => to_html(markdown),
+        MessageFormat::Plaintext => to_plaintext(markdown)
+    }
+}
+
+/// Renders `markdown` and shortens the result to at most `max_length` characters.
+///
+/// Whole lines are dropped from the end, so list items are not cut in half, and
+/// a marker with the number of omitted list items is appended.
+pub fn render_limited(markdown: &str, format: MessageFormat, max_length: Option<usize>) -> String {
+    let rendered = render(markdown, format);
+    let max_length = match max_length {
+        Some(max_length) if rendered.chars().count() > max_length => max_length,
+        _ => return rendered
+    };
+    let lines: Vec<&str> = markdown.lines().collect();
+    for keep in (0..lines.len()).rev() {
+        let omitted = lines[keep..].iter().filter(|line| bullet_item(line).is_some()).count();
+        let marker = match omitted {
+            0 => String::from("... (truncated)"),
+            1 => String::from("... (truncated, 1 more item)"),
+            _ => format!("... (truncated, {} more items)", omitted)
+        };
+        let mut shortened = lines[..keep].join("\n");
+        if !shortened.is_empty() {
+            shortened.push('\n');
+        }
+        shortened.push_str(&marker);
+        let shortened = render(&shortened, format);
+        if shortened.chars().count() <= max_length {
+            return shortened;
+        }
+    }
+    // Not even the marker fits
+    rendered.chars().take(max_length).collect()
+}
+
+fn bullet_item(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    trimmed.strip_prefix("* ")
+        .or_else(|| trimmed.strip_prefix("- "))
+        .map(|item| item.trim())
+}
+
+fn strip_emphasis(line: &str) -> String {
+    line.replace("**", "").replace("__", "").replace('`', "")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut in_list = false;
+    for line in markdown.lines() {
+        match bullet_item(line) {
+            Some(item) => {
+                if !in_list {
+                    html.push_str("<ul>\n");
+                    in_list = true;
+                }
+                html.push_str(&format!("<li>{}</li>\n", escape_html(&strip_emphasis(item))));
+            },
+            None => {
+                if in_list {
+                    html.push_str("</ul>\n");
+                    in_list = false;
+                }
+                if !line.trim().is_empty() {
+                    html.push_str(&format!("{}<br>\n", escape_html(&strip_emphasis(line))));
+                }
+            }
+        }
+    }
+    if in_list {
+        html.push_str("</ul>\n");
+    }
+    html
+}
+
+fn to_plaintext(markdown: &str) -> String {
+    let mut text = String::new();
+    for line in markdown.lines() {
+        match bullet_item(line) {
+            Some(item) => text.push_str(&format!("- {}\n", strip_emphasis(item))),
+            None => text.push_str(&format!("{}\n", strip_emphasis(line)))
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lists_are_cut_between_items() {
+        let markdown = "Free:\n * Center 1\n * Center 2\n * Center 3\n * Center 4\n";
+        assert_eq!(render_limited(markdown, MessageFormat::Markdown, Some(50)), "Free:\n * Center 1\n... (truncated, 3 more items)");
+        assert_eq!(render_limited(markdown, MessageFormat::Markdown, Some(100)), markdown);
+    }
+}
@@ -6,55 +6,166 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use reqwest;
-use std::{error::Error};
-use crate::notification::Notificator;
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
 use async_std::task;
 use crate::config::GotifySettings;
-use std::collections::HashMap;
+use crate::http_client::{ClientPool, status_error};
+use json::object;
+
+/// Longer messages are shortened, as proxies in front of Gotify reject large requests with 413
+const MAX_MESSAGE_LENGTH: usize = 10000;
 
 #[derive(Debug)]
 pub struct Gotify {
     url: String,
     application_token: String,
+    format: MessageFormat,
     client: reqwest::Client
 }
 
 impl Gotify {
-    pub fn new(url: &String, application_token: &String) -> Gotify {
+    pub fn new(url: &str, application_token: &str, format: MessageFormat, client: reqwest::Client) -> Gotify {
         Gotify{
-            url: url.clone(),
-            application_token: application_token.clone(),
-            client: reqwest::Client::new()
+            url: String::from(url),
+            application_token: String::from(application_token),
+            format,
+            client
         }
     }
 
-    pub fn from(settings: &GotifySettings) -> Gotify {
-        Gotify::new(&settings.url, &settings.application_token)
+    pub fn from(settings: &GotifySettings, clients: &ClientPool) -> Gotify {
+        Gotify::new(&settings.url, &settings.application_token, settings.format, clients.get(&settings.url, &settings.proxy, &settings.tls, &settings.network))
     }
 
-    pub async fn send_message(&self, title: &str, message: &str, priority: u16) -> Result<(), Box<dyn Error>> {
+    /// Sends a message, which opens `click_url` when it is clicked in a Gotify client.
+    ///
+    /// Gotify has no tags, so they are sent as `covid-vacc-poll::tags` extras for clients and plugins.
+    pub async fn send_message(&self, title: &str, message: &str, priority: u16, click_url: Option<&str>, tags: &[String]) -> Result<(), AppError> {
         let uri = format!("{}/message?token={}", self.url, self.application_token);
-        let priority = priority.to_string();
-        let mut params = HashMap::new();
-        params.insert("title", title);
-        params.insert("message", message);
-        params.insert("priority", priority.as_str());
-        self.client.post(&uri).form(&params).send().await?;
+        let mut payload = object!{
+            "title" => title,
+            "message" => message,
+            "priority" => priority
+        };
+        if let Some(click_url) = click_url {
+            payload["extras"]["client::notification"] = object!{
+                "click" => object!{"url" => click_url}
+            };
+        }
+        if !tags.is_empty() {
+            payload["extras"]["covid-vacc-poll::tags"] = tags.to_vec().into();
+        }
+        let resp = self.client.post(&uri)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.dump())
+            .send().await?;
+        // The URI is left out, as it contains the token
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(AppError::Notification(format!("Gotify responded with {}", status_error(status, &resp.text().await?))));
+        }
         Ok(())
     }
 
-    pub fn send_message_blocking(&self, title: &str, message: &str, priority: u16) -> Result<(), Box<dyn Error>> {
-        task::block_on(self.send_message(title, message, priority))
+    pub fn send_message_blocking(&self, title: &str, message: &str, priority: u16, click_url: Option<&str>, tags: &[String]) -> Result<(), AppError> {
+        task::block_on(self.send_message(title, message, priority, click_url, tags))
+    }
+
+    fn priority(notification: &Notification) -> u16 {
+        if let Some(priority) = notification.priority {
+            return priority.into();
+        }
+        match notification.severity {
+            Severity::Urgent if notification.important => 10,
+            Severity::Normal => 1,
+            Severity::Urgent => 9
+        }
     }
 }
 
 impl Notificator for Gotify {
-    fn send_normal(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>> {
-        self.send_message_blocking(title, message, 1)
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.send_message_blocking(
+            &notification.title,
+            &notification.body,
+            Self::priority(notification),
+            notification.url.as_deref(),
+            &notification.tags
+        )
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        Some(MAX_MESSAGE_LENGTH)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn click_url_is_sent_as_extras() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.path("/message")
+                .query_param("token", "token")
+                .json_body_partial(r#"{"priority": 9, "extras": {"client::notification": {"click": {"url": "https://booking.example.com"}}}}"#);
+            then.status(200);
+        });
+        let gotify = Gotify::new(&server.base_url(), "token", MessageFormat::Plaintext, reqwest::Client::new());
+
+        let notification = Notification::new("Title", "Message", Severity::Urgent)
+            .with_url(Some(String::from("https://booking.example.com")));
+        gotify.send(&notification).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn tags_are_sent_as_extras() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.path("/message")
+                .json_body_partial(r#"{"extras": {"covid-vacc-poll::tags": ["Berlin", "Mitte"]}}"#);
+            then.status(200);
+        });
+        let gotify = Gotify::new(&server.base_url(), "token", MessageFormat::Plaintext, reqwest::Client::new());
+
+        let notification = Notification::new("Title", "Message", Severity::Normal)
+            .with_tags(vec![String::from("Berlin"), String::from("Mitte")]);
+        gotify.send(&notification).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn numeric_priority_is_sent_as_is() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.path("/message")
+                .json_body_partial(r#"{"priority": 7}"#);
+            then.status(200);
+        });
+        let gotify = Gotify::new(&server.base_url(), "token", MessageFormat::Plaintext, reqwest::Client::new());
+
+        gotify.send(&Notification::new("Title", "Message", Severity::Normal).with_priority(7)).unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn error_status_is_an_error() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.path("/message");
+            then.status(401).body(r#"{"error":"Unauthorized"}"#);
+        });
+        let gotify = Gotify::new(&server.base_url(), "wrong", MessageFormat::Plaintext, reqwest::Client::new());
 
-    fn send_urgent(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>> {
-        self.send_message_blocking(title, message, 9)
+        let error = gotify.send(&Notification::new("Title", "Message", Severity::Normal)).unwrap_err();
+        assert_eq!(error.to_string(), r#"Notification error: Gotify responded with HTTP 401 Unauthorized: {"error":"Unauthorized"}"#);
     }
 }
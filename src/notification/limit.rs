@@ -0,0 +1,49 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification};
+
+/// Lowers the message length limit of a notificator to the configured `max_length`.
+///
+/// The messages are shortened when they are rendered for the notificator.
+#[derive(Debug)]
+pub struct LengthLimit {
+    inner: Box<dyn Notificator>,
+    max_length: usize
+}
+
+impl LengthLimit {
+    pub fn new(inner: Box<dyn Notificator>, max_length: usize) -> LengthLimit {
+        LengthLimit{
+            inner,
+            max_length
+        }
+    }
+}
+
+impl Notificator for LengthLimit {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.inner.send(notification)
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        match self.inner.max_length() {
+            Some(max_length) => Some(max_length.min(self.max_length)),
+            None => Some(self.max_length)
+        }
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+use async_std::task;
+use crate::config::MastodonSettings;
+use crate::http_client::ClientPool;
+use std::collections::HashMap;
+
+/// Part of the character limit kept free for the title, the booking link and the hashtags
+const FOOTER_RESERVE: usize = 150;
+
+/// Posts statuses to a Mastodon account.
+///
+/// Urgent messages are posted with the configured visibility, normal ones
+/// unlisted, so they do not show up in the public timelines.
+#[derive(Debug)]
+pub struct Mastodon {
+    instance_url: String,
+    access_token: String,
+    visibility: String,
+    max_chars: usize,
+    client: reqwest::Client
+}
+
+impl Mastodon {
+    pub fn from(settings: &MastodonSettings, clients: &ClientPool) -> Mastodon {
+        Mastodon{
+            instance_url: settings.instance_url.clone(),
+            access_token: settings.access_token.clone(),
+            visibility: settings.visibility.clone(),
+            max_chars: settings.max_chars as usize,
+            client: clients.default_client()
+        }
+    }
+
+    /// Mastodon posts a status only once per `idempotency_key` within an hour.
+    pub async fn post_status(&self, status: &str, visibility: &str, idempotency_key: Option<&str>) -> Result<(), AppError> {
+        let uri = format!("{}/api/v1/statuses", self.instance_url);
+        let mut params = HashMap::new();
+        params.insert("status", status);
+        params.insert("visibility", visibility);
+        let mut request = self.client.post(&uri)
+            .bearer_auth(&self.access_token)
+            .form(&params);
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        request.send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Joins title, message, link and hashtags.
+    ///
+    /// The message is already shortened to `max_length()` when it is rendered.
+    fn to_status(&self, notification: &Notification) -> String {
+        let mut status = format!("{}\n{}", notification.title, notification.body.trim_end());
+        if let Some(url) = &notification.url {
+            status = format!("{}\n{}", status, url);
+        }
+        if !notification.tags.is_empty() {
+            status = format!("{}\n{}", status, notification.hashtags());
+        }
+        status
+    }
+}
+
+impl Notificator for Mastodon {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let visibility = match notification.severity {
+            Severity::Normal => "unlisted",
+            Severity::Urgent => self.visibility.as_str()
+        };
+        task::block_on(self.post_status(&self.to_status(notification), visibility, notification.idempotency_key.as_deref()))
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Plaintext
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        Some(self.max_chars.saturating_sub(FOOTER_RESERVE).max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::format;
+
+    #[test]
+    fn long_status_is_shortened_between_items_before_the_link() {
+        let settings = MastodonSettings{
+            instance_url: String::from("https://mastodon.example.com"),
+            access_token: String::from("token"),
+            visibility: String::from("public"),
+            max_chars: 220
+        };
+        let mastodon = Mastodon::from(&settings, &ClientPool::new(std::time::Duration::from_secs(10)));
+        let markdown: String = (1..=10).map(|i| format!("* Calendar {}\n", i)).collect();
+        let body = format::render_limited(&markdown, mastodon.format(), mastodon.max_length());
+        let notification = Notification::new("Title", &body, Severity::Urgent)
+            .with_url(Some(String::from("https://b.example.com")));
+
+        let status = mastodon.to_status(&notification);
+        assert!(status.chars().count() <= 220);
+        assert!(status.starts_with("Title\n"));
+        assert!(status.ends_with(" more items)\nhttps://b.example.com"));
+    }
+}
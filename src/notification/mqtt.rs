@@ -0,0 +1,116 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use std::thread;
+use std::time::Duration;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+use crate::notification::reconnect::Reconnect;
+use crate::config::MqttSettings;
+use rumqttc::{Client, ConnectionError, Event, MqttOptions, Packet, QoS};
+use json::object;
+use log::{info, warn};
+use rand::Rng;
+
+/// Publishes notifications to an MQTT broker.
+///
+/// Normal messages go to `topic`, urgent ones to `topic/urgent`. The payload
+/// is a JSON object with `title`, `message`, `tags` and `id`, which is the
+/// same for every retry of a message, so subscribers can drop duplicates. While the broker is
+/// unreachable, messages are queued up to the capacity of the client.
+pub struct Mqtt {
+    broker: String,
+    topic: String,
+    qos: QoS,
+    client: Client,
+    reconnect: Reconnect
+}
+
+impl std::fmt::Debug for Mqtt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Mqtt")
+            .field("broker", &self.broker)
+            .field("topic", &self.topic)
+            .field("qos", &self.qos)
+            .finish()
+    }
+}
+
+impl Mqtt {
+    pub fn from(settings: &MqttSettings) -> Mqtt {
+        let client_id = format!("covid-vacc-poll-{:08x}", rand::thread_rng().gen::<u32>());
+        let mut options = MqttOptions::new(client_id, settings.broker_host.as_str(), settings.broker_port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &settings.username {
+            options.set_credentials(username.as_str(), settings.password.clone().unwrap_or_default());
+        }
+        let (client, mut connection) = Client::new(options, 10);
+
+        // The connection has to be polled continuously to deliver publishes and
+        // to keep the session alive. It reconnects on the next poll after an error.
+        // The thread ends when the client is dropped, e.g. on a reload.
+        let broker = format!("{}:{}", settings.broker_host, settings.broker_port);
+        let reconnect = Reconnect::new(Duration::from_secs(settings.reconnect_delay_secs as u64));
+        let mut thread_reconnect = reconnect.clone();
+        let thread_broker = broker.clone();
+        thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("MQTT connected to {}", thread_broker);
+                        thread_reconnect.connected();
+                    },
+                    Ok(_) => {},
+                    Err(ConnectionError::RequestsDone) => break,
+                    Err(error) => {
+                        let delay = thread_reconnect.failed();
+                        warn!("MQTT connection to {} failed: {}. Reconnecting in {} s.", thread_broker, error, delay.as_secs());
+                        thread::sleep(delay);
+                    }
+                }
+            }
+        });
+
+        Mqtt{
+            broker,
+            topic: settings.topic.clone(),
+            qos: rumqttc::qos(settings.qos).unwrap_or(QoS::AtMostOnce),
+            client,
+            reconnect
+        }
+    }
+
+    fn publish(&self, topic: String, title: &str, message: &str, tags: &[String], id: Option<&str>) -> Result<(), AppError> {
+        let payload = object!{
+            "title" => title,
+            "message" => message,
+            "tags" => tags.to_vec(),
+            "id" => id
+        };
+        // try_publish does not block the service when the queue is full
+        match self.client.try_publish(topic, self.qos, false, payload.dump()) {
+            Ok(_) => Ok(()),
+            Err(_) if !self.reconnect.is_connected() => Err(AppError::Notification(format!("MQTT broker {} is not connected and the queue is full", self.broker))),
+            Err(error) => Err(AppError::Notification(error.to_string()))
+        }
+    }
+}
+
+impl Notificator for Mqtt {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let topic = match notification.severity {
+            Severity::Normal => self.topic.clone(),
+            Severity::Urgent => format!("{}/urgent", self.topic)
+        };
+        self.publish(topic, &notification.title, &notification.body, &notification.tags, notification.idempotency_key.as_deref())
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Plaintext
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+use async_std::task;
+use crate::config::PushbulletSettings;
+use crate::http_client::ClientPool;
+use crate::i18n::Language;
+use json::{object, JsonValue};
+
+const PUSHES_URL: &str = "https://api.pushbullet.com/v2/pushes";
+
+/// Pushes notes to the devices of a Pushbullet account.
+///
+/// Pushbullet has no priorities, so urgent pushes get a marker in front of the title.
+#[derive(Debug)]
+pub struct Pushbullet {
+    access_token: String,
+    device_iden: Option<String>,
+    language: Language,
+    client: reqwest::Client
+}
+
+impl Pushbullet {
+    pub fn from(settings: &PushbulletSettings, clients: &ClientPool) -> Pushbullet {
+        Pushbullet{
+            access_token: settings.access_token.clone(),
+            device_iden: settings.device_iden.clone(),
+            language: settings.language,
+            client: clients.default_client()
+        }
+    }
+
+    pub async fn push(&self, push: JsonValue) -> Result<(), AppError> {
+        self.client.post(PUSHES_URL)
+            .header("Access-Token", self.access_token.as_str())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(push.dump())
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Builds a note, or a link push if the notification has a URL.
+    fn to_push(&self, notification: &Notification) -> JsonValue {
+        let title = match notification.severity {
+            Severity::Normal => notification.title.clone(),
+            Severity::Urgent => format!("{}{}", self.language.texts().urgent_prefix, notification.title)
+        };
+        let mut push = object!{
+            "type" => "note",
+            "title" => title,
+            "body" => notification.body.as_str()
+        };
+        if let Some(url) = &notification.url {
+            push["type"] = "link".into();
+            push["url"] = url.as_str().into();
+        }
+        if let Some(device_iden) = &self.device_iden {
+            push["device_iden"] = device_iden.as_str().into();
+        }
+        // Pushbullet ignores a push with a guid it already has
+        if let Some(key) = &notification.idempotency_key {
+            push["guid"] = key.as_str().into();
+        }
+        push
+    }
+}
+
+impl Notificator for Pushbullet {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        task::block_on(self.push(self.to_push(notification)))
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Plaintext
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urgent_push_is_prefixed_and_addressed_to_the_device() {
+        let settings = PushbulletSettings{
+            access_token: String::from("token"),
+            device_iden: Some(String::from("phone")),
+            language: Language::En
+        };
+        let pushbullet = Pushbullet::from(&settings, &ClientPool::new(std::time::Duration::from_secs(10)));
+
+        let push = pushbullet.to_push(&Notification::new("Title", "Body", Severity::Urgent));
+        assert_eq!(push["type"], "note");
+        assert_eq!(push["title"], "Urgent: Title");
+        assert_eq!(push["body"], "Body");
+        assert_eq!(push["device_iden"], "phone");
+    }
+}
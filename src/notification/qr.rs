@@ -0,0 +1,128 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification};
+use base64::Engine;
+use qrcode::QrCode;
+use qrcode::render::svg;
+use log::warn;
+
+/// More QR codes would make the message too long to be useful
+const MAX_QR_CODES: usize = 3;
+
+/// Appends QR codes of the booking links, so they can be scanned from another device.
+///
+/// The codes are embedded as SVG data URIs, as images in markdown and HTML
+/// messages, so only notificators which show such images are wrapped.
+/// Plaintext messages are sent unchanged. Without booking links of
+/// the freed calendars, the link of the notification is used. The body is
+/// already shortened to the length limit of the wrapped notificator, including
+/// a configured `max_length`, when it arrives, so codes which would exceed the
+/// limit are left out.
+#[derive(Debug)]
+pub struct QrCodes {
+    inner: Box<dyn Notificator>
+}
+
+impl QrCodes {
+    pub fn new(inner: Box<dyn Notificator>) -> QrCodes {
+        QrCodes{
+            inner
+        }
+    }
+}
+
+/// Renders `url` as a QR code in an SVG data URI.
+fn data_uri(url: &str) -> Option<String> {
+    let code = match QrCode::new(url.as_bytes()) {
+        Ok(code) => code,
+        Err(error) => {
+            warn!("No QR code for {}: {}", url, error);
+            return None;
+        }
+    };
+    let image = code.render::<svg::Color>().min_dimensions(200, 200).build();
+    Some(format!("data:image/svg+xml;base64,{}", base64::engine::general_purpose::STANDARD.encode(image)))
+}
+
+fn append_codes(body: &str, urls: &[String], format: MessageFormat, max_length: Option<usize>) -> String {
+    let mut body = String::from(body);
+    let mut length = body.chars().count();
+    for url in urls.iter().take(MAX_QR_CODES) {
+        let image = match data_uri(url) {
+            Some(image) => image,
+            None => continue
+        };
+        let code = match format {
+            MessageFormat::Markdown => format!("\n\n![QR code of {}]({})", url, image),
+            MessageFormat::Html => format!("<br><img src=\"{}\" alt=\"QR code of {}\">", image, url),
+            MessageFormat::Plaintext => continue
+        };
+        let code_length = code.chars().count();
+        if max_length.is_some_and(|max_length| length + code_length > max_length) {
+            warn!("QR code of {} left out, as the message would exceed the length limit", url);
+            continue;
+        }
+        body.push_str(&code);
+        length += code_length;
+    }
+    body
+}
+
+impl Notificator for QrCodes {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let urls = if notification.booking_urls.is_empty() {
+            notification.url.iter().cloned().collect()
+        } else {
+            notification.booking_urls.clone()
+        };
+        self.inner.send(&Notification{
+            body: append_codes(&notification.body, &urls, self.inner.format(), self.inner.max_length()),
+            ..notification.clone()
+        })
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        self.inner.max_length()
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_images_in_markdown_and_html() {
+        let urls = vec![String::from("https://example.com/booking/1")];
+        let markdown = append_codes("free", &urls, MessageFormat::Markdown, None);
+        assert!(markdown.starts_with("free\n\n![QR code of https://example.com/booking/1](data:image/svg+xml;base64,"));
+        let html = append_codes("<p>free</p>", &urls, MessageFormat::Html, None);
+        assert!(html.starts_with("<p>free</p><br><img src=\"data:image/svg+xml;base64,"));
+        assert!(html.ends_with("\" alt=\"QR code of https://example.com/booking/1\">"));
+        assert_eq!(append_codes("free", &urls, MessageFormat::Plaintext, None), "free");
+    }
+
+    #[test]
+    fn codes_exceeding_the_length_limit_are_left_out() {
+        let urls = vec![String::from("https://example.com/booking/1"), String::from("https://example.com/booking/2")];
+        let one_code = append_codes("free", &urls[..1], MessageFormat::Markdown, None).chars().count();
+
+        let limited = append_codes("free", &urls, MessageFormat::Markdown, Some(one_code + 10));
+        assert_eq!(limited.chars().count(), one_code);
+        assert_eq!(append_codes("free", &urls, MessageFormat::Markdown, Some(100)), "free");
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use std::sync::Mutex;
+use std::time::Instant;
+use log::warn;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant
+}
+
+/// Limits a notificator to `max_per_minute` messages using a token bucket.
+///
+/// Normal messages exceeding the limit are dropped and logged. Urgent ones
+/// are always sent, as they announce free appointments, but use up a token.
+#[derive(Debug)]
+pub struct RateLimited<T: Notificator> {
+    inner: T,
+    max_per_minute: Option<u32>,
+    bucket: Mutex<TokenBucket>
+}
+
+impl<T: Notificator> RateLimited<T> {
+    /// Wraps `inner`. Without `max_per_minute`, all messages pass.
+    pub fn new(inner: T, max_per_minute: Option<u32>) -> RateLimited<T> {
+        RateLimited{
+            inner,
+            max_per_minute,
+            bucket: Mutex::new(TokenBucket{
+                tokens: max_per_minute.unwrap_or(0) as f64,
+                last_refill: Instant::now()
+            })
+        }
+    }
+
+    fn take_token(&self) -> bool {
+        let max_per_minute = match self.max_per_minute {
+            Some(max) => max as f64,
+            None => return true
+        };
+        let mut bucket = match self.bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last_refill).as_secs_f64() * max_per_minute / 60.0;
+        bucket.tokens = (bucket.tokens + refill).min(max_per_minute);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<T: Notificator> Notificator for RateLimited<T> {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        if !self.take_token() {
+            match notification.severity {
+                Severity::Normal => {
+                    warn!("Rate limit exceeded. Dropping message: {}", notification.title);
+                    return Ok(());
+                },
+                Severity::Urgent => warn!("Rate limit exceeded. Sending urgent message anyway: {}", notification.title)
+            }
+        }
+        self.inner.send(notification)
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.inner.max_length()
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::recording::RecordingNotificator;
+
+    #[test]
+    fn normal_messages_over_the_limit_are_dropped() {
+        let limited = RateLimited::new(RecordingNotificator::default(), Some(2));
+        let normal = Notification::new("Normal", "Body", Severity::Normal);
+
+        limited.send(&normal).unwrap();
+        limited.send(&normal).unwrap();
+        limited.send(&normal).unwrap();
+        limited.send(&Notification::new("Urgent", "Body", Severity::Urgent)).unwrap();
+        let titles: Vec<String> = limited.inner.sent().into_iter().map(|(_, title, _)| title).collect();
+        assert_eq!(titles, vec!["Normal", "Normal", "Urgent"]);
+    }
+}
@@ -0,0 +1,76 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Longest wait between two connection attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
+
+/// Connection state of a notificator with a long-lived connection.
+///
+/// The thread driving the connection calls `failed()` after every error and
+/// waits for the returned delay, which doubles up to 5 minutes. `connected()`
+/// resets it. Senders use `is_connected()` to fail fast while the connection is down.
+#[derive(Debug, Clone)]
+pub struct Reconnect {
+    initial_delay: Duration,
+    delay: Duration,
+    connected: Arc<AtomicBool>
+}
+
+impl Reconnect {
+    pub fn new(initial_delay: Duration) -> Reconnect {
+        Reconnect{
+            initial_delay,
+            delay: initial_delay,
+            connected: Arc::new(AtomicBool::new(false))
+        }
+    }
+
+    /// Marks the connection as up and resets the delay.
+    pub fn connected(&mut self) {
+        self.delay = self.initial_delay;
+        self.connected.store(true, Ordering::Relaxed);
+    }
+
+    /// Marks the connection as down and returns how long to wait before the next attempt.
+    pub fn failed(&mut self) -> Duration {
+        self.connected.store(false, Ordering::Relaxed);
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(MAX_RECONNECT_DELAY.max(self.initial_delay));
+        delay
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_until_connected() {
+        let mut reconnect = Reconnect::new(Duration::from_secs(100));
+        let sender = reconnect.clone();
+        reconnect.connected();
+        assert!(sender.is_connected());
+
+        assert_eq!(reconnect.failed(), Duration::from_secs(100));
+        assert!(!sender.is_connected());
+        assert_eq!(reconnect.failed(), Duration::from_secs(200));
+        assert_eq!(reconnect.failed(), Duration::from_secs(300));
+        assert_eq!(reconnect.failed(), Duration::from_secs(300));
+
+        reconnect.connected();
+        assert_eq!(reconnect.failed(), Duration::from_secs(100));
+    }
+}
@@ -0,0 +1,56 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::sync::{Arc, Mutex};
+use crate::error::AppError;
+use crate::notification::{Notificator, Notification, NotificatorSubCollection, Severity};
+
+/// Stores the messages instead of sending them, for assertions in tests.
+#[derive(Debug, Default)]
+pub struct RecordingNotificator {
+    sent: Mutex<Vec<(Severity, String, String)>>,
+    /// Every send fails after recording the message
+    fail: bool
+}
+
+impl RecordingNotificator {
+    pub fn new() -> Arc<Mutex<RecordingNotificator>> {
+        Arc::new(Mutex::new(RecordingNotificator::default()))
+    }
+
+    pub fn failing() -> Arc<Mutex<RecordingNotificator>> {
+        Arc::new(Mutex::new(RecordingNotificator{fail: true, ..Default::default()}))
+    }
+
+    /// Severity, title and body of every message, in the order they were sent.
+    pub fn sent(&self) -> Vec<(Severity, String, String)> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl Notificator for RecordingNotificator {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.sent.lock().unwrap().push((notification.severity, notification.title.clone(), notification.body.clone()));
+        if self.fail {
+            Err(AppError::Notification(String::from("down")))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Sends to `notificators`, named `n0`, `n1` and so on.
+pub fn subcollection(notificators: &[Arc<Mutex<RecordingNotificator>>]) -> NotificatorSubCollection {
+    NotificatorSubCollection{
+        notificators: notificators.iter()
+            .enumerate()
+            .map(|(i, notif)| (format!("n{}", i), notif.clone() as Arc<Mutex<dyn Notificator>>))
+            .collect(),
+        fail_fast: false
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::warn;
+use crate::notification::{Notificator, MessageFormat, Notification};
+
+/// Derives a key from the content of a message and the time it is first sent.
+///
+/// The time keeps a later message with the same text, e.g. about a calendar
+/// which is free again, from being dropped as a duplicate.
+fn idempotency_key(notification: &Notification, first_attempt: SystemTime) -> String {
+    let mut hasher = DefaultHasher::new();
+    notification.title.hash(&mut hasher);
+    notification.body.hash(&mut hasher);
+    notification.severity.hash(&mut hasher);
+    notification.url.hash(&mut hasher);
+    first_attempt.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Wraps a notificator and retries failed sends with exponential backoff.
+#[derive(Debug)]
+pub struct Retry {
+    inner: Box<dyn Notificator>,
+    max_retries: u32,
+    delay: Duration
+}
+
+impl Retry {
+    pub fn new(inner: Box<dyn Notificator>, max_retries: u32, delay: Duration) -> Retry {
+        Retry{
+            inner,
+            max_retries,
+            delay
+        }
+    }
+
+    fn with_retries<F>(&self, send: F) -> Result<(), AppError>
+        where F: Fn(&dyn Notificator) -> Result<(), AppError>
+    {
+        let mut attempt: u32 = 0;
+        loop {
+            match send(self.inner.as_ref()) {
+                Ok(_) => return Ok(()),
+                Err(error) => {
+                    if attempt >= self.max_retries {
+                        return Err(error);
+                    }
+                    let delay = self.delay.checked_mul(2u32.saturating_pow(attempt)).unwrap_or(self.delay);
+                    warn!("Sending notification failed (attempt {} of {}): {}. Retrying in {} s.",
+                          attempt + 1, self.max_retries + 1, error, delay.as_secs());
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Notificator for Retry {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        // An attempt which timed out may still have been delivered
+        let mut notification = notification.clone();
+        if notification.idempotency_key.is_none() {
+            notification.idempotency_key = Some(idempotency_key(&notification, SystemTime::now()));
+        }
+        self.with_retries(|notif| notif.send(&notification))
+    }
+
+    fn format(&self) -> MessageFormat {
+        self.inner.format()
+    }
+    fn max_length(&self) -> Option<usize> {
+        self.inner.max_length()
+    }
+
+    fn shows_images(&self) -> bool {
+        self.inner.shows_images()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::Severity;
+    use std::sync::{Arc, Mutex};
+
+    /// Fails the first send and records the key of every attempt.
+    #[derive(Debug)]
+    struct FlakyNotificator {
+        keys: Arc<Mutex<Vec<Option<String>>>>
+    }
+
+    impl Notificator for FlakyNotificator {
+        fn send(&self, notification: &Notification) -> Result<(), AppError> {
+            let mut keys = self.keys.lock().unwrap();
+            keys.push(notification.idempotency_key.clone());
+            if keys.len() == 1 {
+                return Err(AppError::Notification(String::from("timeout")));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retries_share_the_idempotency_key() {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let retry = Retry::new(Box::new(FlakyNotificator{keys: keys.clone()}), 1, Duration::ZERO);
+        retry.send(&Notification::new("T", "free", Severity::Urgent)).unwrap();
+
+        let keys = keys.lock().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys[0].is_some());
+        assert_eq!(keys[0], keys[1]);
+    }
+}
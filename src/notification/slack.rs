@@ -0,0 +1,185 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, Notification, Severity};
+use async_std::task;
+use crate::config::SlackSettings;
+use crate::http_client::ClientPool;
+use crate::i18n::Language;
+use json::{array, object, JsonValue};
+
+/// Longest text of a Block Kit section, longer ones are rejected with `invalid_blocks`
+const MAX_SECTION_LENGTH: usize = 3000;
+/// Longest text of a field of a section
+const MAX_FIELD_LENGTH: usize = 2000;
+/// Part of the first section kept free for the mention and the title
+const TITLE_RESERVE: usize = 100;
+
+#[derive(Debug)]
+pub struct Slack {
+    webhook_url: String,
+    channel: Option<String>,
+    language: Language,
+    client: reqwest::Client
+}
+
+impl Slack {
+    pub fn from(settings: &SlackSettings, clients: &ClientPool) -> Slack {
+        Slack{
+            webhook_url: settings.webhook_url.clone(),
+            channel: settings.channel.clone(),
+            language: settings.language,
+            client: clients.default_client()
+        }
+    }
+
+    /// Posts `blocks`, with `text` as fallback for clients which cannot show them.
+    pub async fn send_message(&self, text: &str, blocks: JsonValue) -> Result<(), AppError> {
+        let mut payload = object!{
+            "text" => text,
+            "blocks" => blocks
+        };
+        if let Some(channel) = &self.channel {
+            payload["channel"] = channel.as_str().into();
+        }
+        self.client.post(&self.webhook_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.dump())
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub fn send_message_blocking(&self, text: &str, blocks: JsonValue) -> Result<(), AppError> {
+        task::block_on(self.send_message(text, blocks))
+    }
+
+    /// Lays out the message as Block Kit sections, listing the calendars side by side.
+    fn to_blocks(&self, text: &str, notification: &Notification) -> JsonValue {
+        let texts = self.language.texts();
+        // The body is shortened when it is rendered, a long title is cut here
+        let text: String = text.chars().take(MAX_SECTION_LENGTH).collect();
+        let mut blocks = array![object!{
+            "type" => "section",
+            "text" => object!{"type" => "mrkdwn", "text" => text}
+        }];
+        let mut fields = JsonValue::new_array();
+        for (label, names) in [(texts.newly_free, &notification.added), (texts.no_longer_free, &notification.removed)] {
+            if !names.is_empty() {
+                fields.push(object!{"type" => "mrkdwn", "text" => Self::field_list(label, names)}).unwrap();
+            }
+        }
+        if !fields.is_empty() {
+            blocks.push(object!{"type" => "section", "fields" => fields}).unwrap();
+        }
+        if let Some(url) = &notification.url {
+            blocks.push(object!{
+                "type" => "section",
+                "text" => object!{"type" => "mrkdwn", "text" => format!("<{}|{}>", url, texts.book_now)}
+            }).unwrap();
+        }
+        if !notification.tags.is_empty() {
+            blocks.push(object!{
+                "type" => "context",
+                "elements" => array![object!{"type" => "mrkdwn", "text" => notification.hashtags()}]
+            }).unwrap();
+        }
+        blocks
+    }
+
+    /// Lists `names` under `label`, leaving out the names which exceed the field length.
+    fn field_list(label: &str, names: &[String]) -> String {
+        let mut list = format!("*{}*\n", label);
+        for (index, name) in names.iter().enumerate() {
+            let item = format!("• {}\n", name);
+            let remaining = names.len() - index - 1;
+            // The marker of the names after this one must still fit
+            let needed = if remaining == 0 { 0 } else { format!("• … +{}\n", remaining).chars().count() };
+            if list.chars().count() + item.chars().count() + needed > MAX_FIELD_LENGTH {
+                list.push_str(&format!("• … +{}\n", names.len() - index));
+                break;
+            }
+            list.push_str(&item);
+        }
+        list
+    }
+
+    /// Converts markdown bullets and links to Slack mrkdwn, which has no list syntax.
+    fn to_mrkdwn(message: &str) -> String {
+        let mut text = String::new();
+        for line in message.lines() {
+            let line = Self::convert_links(line);
+            let trimmed = line.trim_start();
+            match trimmed.strip_prefix("* ").or_else(|| trimmed.strip_prefix("- ")) {
+                Some(item) => text.push_str(&format!("• {}\n", item.trim())),
+                None => text.push_str(&format!("{}\n", line))
+            }
+        }
+        text
+    }
+
+    /// Rewrites markdown links `[name](url)` as `<url|name>`.
+    fn convert_links(line: &str) -> String {
+        let mut converted = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('[') {
+            let link = rest[start + 1..].split_once("](")
+                .and_then(|(name, tail)| tail.split_once(')').map(|(url, tail)| (name, url, tail)));
+            match link {
+                Some((name, url, tail)) if !name.contains(']') => {
+                    converted.push_str(&rest[..start]);
+                    converted.push_str(&format!("<{}|{}>", url, name));
+                    rest = tail;
+                },
+                _ => {
+                    converted.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        converted.push_str(rest);
+        converted
+    }
+}
+
+impl Notificator for Slack {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mention = match notification.severity {
+            Severity::Normal => "",
+            Severity::Urgent => "<!channel> "
+        };
+        let text = format!("{}*{}*\n{}", mention, notification.title, Self::to_mrkdwn(&notification.body));
+        self.send_message_blocking(text.as_str(), self.to_blocks(&text, notification))
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        Some(MAX_SECTION_LENGTH - TITLE_RESERVE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_and_bullets_are_converted_to_mrkdwn() {
+        let text = Slack::to_mrkdwn("Free:\n* [Center 1](https://example.com/1) and [x] rest\n- Center 2");
+        assert_eq!(text, "Free:\n• <https://example.com/1|Center 1> and [x] rest\n• Center 2\n");
+    }
+
+    #[test]
+    fn long_calendar_lists_fit_into_a_field() {
+        let names: Vec<String> = (0..500).map(|i| format!("Calendar {}", i)).collect();
+        let list = Slack::field_list("Newly free", &names);
+        assert!(list.chars().count() <= MAX_FIELD_LENGTH);
+        assert!(list.starts_with("*Newly free*\n• Calendar 0\n"));
+        assert!(list.lines().last().unwrap().starts_with("• … +"));
+        assert_eq!(Slack::field_list("Newly free", &names[..2]), "*Newly free*\n• Calendar 0\n• Calendar 1\n");
+    }
+}
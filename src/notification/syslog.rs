@@ -0,0 +1,102 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fmt;
+use std::process;
+use std::str::FromStr;
+use std::sync::Mutex;
+use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+use crate::config::{SyslogSettings, SyslogTransport};
+
+/// Writes messages to the local or a remote syslog.
+///
+/// Normal messages are logged with severity `info`, urgent ones with
+/// `warning`. The connection is opened on the first message and reopened
+/// after a failed write.
+pub struct Syslog {
+    facility: Facility,
+    transport: SyslogTransport,
+    logger: Mutex<Option<Logger<LoggerBackend, Formatter3164>>>
+}
+
+impl Syslog {
+    pub fn from(settings: &SyslogSettings) -> Syslog {
+        Syslog{
+            // Validated when the config is loaded
+            facility: Facility::from_str(settings.facility.as_str()).unwrap_or(Facility::LOG_USER),
+            transport: settings.transport.clone(),
+            logger: Mutex::new(None)
+        }
+    }
+
+    fn connect(&self) -> Result<Logger<LoggerBackend, Formatter3164>, AppError> {
+        let formatter = Formatter3164{
+            facility: self.facility,
+            hostname: None,
+            process: String::from("covid-vacc-poll"),
+            pid: process::id()
+        };
+        let logger = match &self.transport {
+            SyslogTransport::Local => syslog::unix(formatter),
+            SyslogTransport::Udp(server) => syslog::udp(formatter, "0.0.0.0:0", server.as_str()),
+            SyslogTransport::Tcp(server) => syslog::tcp(formatter, server.as_str())
+        };
+        logger.map_err(|error| AppError::Notification(format!("Could not connect to syslog: {}", error)))
+    }
+
+    fn log(&self, message: String, urgent: bool) -> Result<(), AppError> {
+        let mut logger = match self.logger.lock() {
+            Ok(logger) => logger,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        if logger.is_none() {
+            *logger = Some(self.connect()?);
+        }
+        let result = match logger.as_mut() {
+            Some(handle) if urgent => handle.warning(message),
+            Some(handle) => handle.info(message),
+            None => Ok(())
+        };
+        if let Err(error) = result {
+            // Reconnect with the next message
+            *logger = None;
+            return Err(AppError::Notification(format!("Writing to syslog failed: {}", error)));
+        }
+        Ok(())
+    }
+
+    /// Syslog entries are single lines, so the message lines are joined.
+    fn to_line(title: &str, message: &str) -> String {
+        let lines: Vec<&str> = message.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        format!("{}: {}", title, lines.join("; "))
+    }
+}
+
+impl fmt::Debug for Syslog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Syslog")
+            .field("facility", &self.facility)
+            .field("transport", &self.transport)
+            .finish()
+    }
+}
+
+impl Notificator for Syslog {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.log(Self::to_line(&notification.title, &notification.body), notification.severity == Severity::Urgent)
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Plaintext
+    }
+}
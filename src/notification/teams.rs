@@ -0,0 +1,111 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, Notification, Severity};
+use async_std::task;
+use crate::config::TeamsSettings;
+use crate::http_client::ClientPool;
+use crate::i18n::Language;
+use json::{array, object, JsonValue};
+
+/// Theme color of the card for normal messages
+const COLOR_NORMAL: &str = "2EB886";
+/// Theme color of the card for urgent messages
+const COLOR_URGENT: &str = "D32F2F";
+
+/// Posts message cards to a Microsoft Teams incoming webhook.
+#[derive(Debug)]
+pub struct Teams {
+    webhook_url: String,
+    language: Language,
+    client: reqwest::Client
+}
+
+impl Teams {
+    pub fn from(settings: &TeamsSettings, clients: &ClientPool) -> Teams {
+        Teams{
+            webhook_url: settings.webhook_url.clone(),
+            language: settings.language,
+            client: clients.default_client()
+        }
+    }
+
+    pub async fn send_card(&self, card: JsonValue) -> Result<(), AppError> {
+        self.client.post(&self.webhook_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(card.dump())
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub fn send_card_blocking(&self, card: JsonValue) -> Result<(), AppError> {
+        task::block_on(self.send_card(card))
+    }
+
+    /// Builds a MessageCard, which renders the markdown of the `text` field.
+    ///
+    /// Teams rejects payloads without the card structure.
+    fn to_card(&self, notification: &Notification) -> JsonValue {
+        let mut card = object!{
+            "@type" => "MessageCard",
+            "@context" => "https://schema.org/extensions",
+            "themeColor" => match notification.severity {
+                Severity::Normal => COLOR_NORMAL,
+                Severity::Urgent => COLOR_URGENT
+            },
+            "summary" => notification.title.as_str(),
+            "title" => notification.title.as_str(),
+            "text" => notification.body.as_str()
+        };
+        if !notification.tags.is_empty() {
+            card["sections"] = array![object!{
+                "facts" => array![object!{"name" => "Tags", "value" => notification.tags.join(", ")}]
+            }];
+        }
+        if let Some(url) = &notification.url {
+            card["potentialAction"] = array![object!{
+                "@type" => "OpenUri",
+                "name" => self.language.texts().book_now,
+                "targets" => array![object!{"os" => "default", "uri" => url.as_str()}]
+            }];
+        }
+        card
+    }
+}
+
+impl Notificator for Teams {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        self.send_card_blocking(self.to_card(notification))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urgent_card_is_red_and_links_the_booking_page() {
+        let notification = Notification::new("Title", "* Calendar", Severity::Urgent)
+            .with_url(Some(String::from("https://b.example.com")));
+
+        let teams = Teams{
+            webhook_url: String::from("https://example.webhook.office.com"),
+            language: Language::En,
+            client: reqwest::Client::new()
+        };
+
+        let card = teams.to_card(&notification);
+        assert_eq!(card["@type"], "MessageCard");
+        assert_eq!(card["themeColor"], COLOR_URGENT);
+        assert_eq!(card["text"], "* Calendar");
+        assert_eq!(card["potentialAction"][0]["name"], "Book now");
+        assert_eq!(card["potentialAction"][0]["targets"][0]["uri"], "https://b.example.com");
+    }
+}
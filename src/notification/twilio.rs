@@ -0,0 +1,167 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::notification::{Notificator, MessageFormat, Notification, Severity};
+use async_std::task;
+use crate::config::TwilioSettings;
+use crate::http_client::ClientPool;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+const API_URL: &str = "https://api.twilio.com";
+/// Maximum length of an SMS body accepted by Twilio
+const MAX_BODY_LENGTH: usize = 1600;
+/// Part of the SMS body kept free for the title when the message is shortened
+const TITLE_RESERVE: usize = 100;
+
+/// Sends SMS through the Twilio Messages API.
+///
+/// Since every SMS costs money, normal messages are only sent if enabled.
+/// A retry of a message, recognized by its idempotency key, is only sent to
+/// the recipients which did not get it yet.
+#[derive(Debug)]
+pub struct Twilio {
+    api_url: String,
+    account_sid: String,
+    auth_token: String,
+    from: String,
+    to: Vec<String>,
+    send_normal: bool,
+    client: reqwest::Client,
+    /// Idempotency key of the last message and the recipients it was delivered to
+    delivered: Mutex<(Option<String>, HashSet<String>)>
+}
+
+impl Twilio {
+    pub fn from(settings: &TwilioSettings, clients: &ClientPool) -> Twilio {
+        Twilio{
+            api_url: String::from(API_URL),
+            account_sid: settings.account_sid.clone(),
+            auth_token: settings.auth_token.clone(),
+            from: settings.from.clone(),
+            to: settings.to.clone(),
+            send_normal: settings.send_normal,
+            client: clients.default_client(),
+            delivered: Mutex::new((None, HashSet::new()))
+        }
+    }
+
+    fn lock_delivered(&self) -> MutexGuard<'_, (Option<String>, HashSet<String>)> {
+        match self.delivered.lock() {
+            Ok(delivered) => delivered,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    /// Returns the recipients which did not get the message with this idempotency key yet.
+    fn pending_recipients(&self, idempotency_key: Option<&str>) -> Vec<String> {
+        let mut delivered = self.lock_delivered();
+        if idempotency_key.is_none() || delivered.0.as_deref() != idempotency_key {
+            *delivered = (idempotency_key.map(String::from), HashSet::new());
+        }
+        self.to.iter()
+            .filter(|to| !delivered.1.contains(*to))
+            .cloned()
+            .collect()
+    }
+
+    async fn send_sms_to(&self, to: &str, body: &str) -> Result<(), AppError> {
+        let uri = format!("{}/2010-04-01/Accounts/{}/Messages.json", self.api_url, self.account_sid);
+        let mut params = HashMap::new();
+        params.insert("From", self.from.as_str());
+        params.insert("To", to);
+        params.insert("Body", body);
+        self.client.post(&uri)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&params)
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Sends the SMS to every recipient, a failed one does not stop the others.
+    pub async fn send_sms(&self, body: &str, idempotency_key: Option<&str>) -> Result<(), AppError> {
+        let body: String = body.chars().take(MAX_BODY_LENGTH).collect();
+        let mut errors = Vec::new();
+        for to in self.pending_recipients(idempotency_key) {
+            match self.send_sms_to(&to, &body).await {
+                Ok(()) => {
+                    self.lock_delivered().1.insert(to);
+                },
+                Err(error) => errors.push(format!("{}: {}", to, error))
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Notification(format!("SMS could not be sent to {} of {} recipients. {}", errors.len(), self.to.len(), errors.join("; "))))
+        }
+    }
+
+    pub fn send_sms_blocking(&self, title: &str, message: &str, idempotency_key: Option<&str>) -> Result<(), AppError> {
+        task::block_on(self.send_sms(format!("{}\n{}", title, message).as_str(), idempotency_key))
+    }
+}
+
+impl Notificator for Twilio {
+    fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        if notification.severity == Severity::Normal && !self.send_normal {
+            return Ok(());
+        }
+        self.send_sms_blocking(&notification.title, &notification.body, notification.idempotency_key.as_deref())
+    }
+
+    fn format(&self) -> MessageFormat {
+        MessageFormat::Plaintext
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        Some(MAX_BODY_LENGTH - TITLE_RESERVE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[test]
+    fn retry_skips_the_recipients_which_got_the_message() {
+        let server = MockServer::start();
+        let first = server.mock(|when, then| {
+            when.path("/2010-04-01/Accounts/AC1/Messages.json").body_contains("To=%2B491");
+            then.status(201);
+        });
+        let second = server.mock(|when, then| {
+            when.path("/2010-04-01/Accounts/AC1/Messages.json").body_contains("To=%2B492");
+            then.status(500);
+        });
+        let twilio = Twilio{
+            api_url: server.base_url(),
+            account_sid: String::from("AC1"),
+            auth_token: String::from("token"),
+            from: String::from("+490"),
+            to: vec![String::from("+491"), String::from("+492")],
+            send_normal: false,
+            client: reqwest::Client::new(),
+            delivered: Mutex::new((None, HashSet::new()))
+        };
+        let mut notification = Notification::new("Title", "Message", Severity::Urgent);
+        notification.idempotency_key = Some(String::from("key"));
+
+        assert!(twilio.send(&notification).unwrap_err().to_string().contains("SMS could not be sent to 1 of 2 recipients. +492: "));
+        assert!(twilio.send(&notification).is_err());
+        first.assert_hits(1);
+        second.assert_hits(2);
+
+        notification.idempotency_key = Some(String::from("next"));
+        assert!(twilio.send(&notification).is_err());
+        first.assert_hits(2);
+    }
+}
@@ -7,174 +7,1144 @@
  */
 
 mod booked4us;
+mod doctolib;
 
-use std::error::Error;
+use crate::error::AppError;
 use std::fmt::Debug;
-// use std::fmt::Display;
+use std::collections::{HashMap, HashSet};
 use std::thread;
-use std::sync::{mpsc, Arc, Mutex};
-use crate::config::{Config, ServiceProviderSettings};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, TryLockError};
+use crate::config::{Config, ServiceProviderSettings, ServiceSettings};
 use booked4us::Booked4us;
-use crate::notification::{NotificatorSubCollection, NotificatorCollection, Notificator, AdminNotificationsSender, AdminNotifications};
-use std::time::Duration;
-use log::{info, error};
+use doctolib::Doctolib;
+use crate::notification::{NotificatorSubCollection, NotificatorCollection, Notificator, AdminNotificationsSender, AdminNotifications, Notification, Severity, URGENT_PRIORITY, MAX_PRIORITY};
+use std::time::{Duration, Instant};
+use log::{info, warn, error};
+use rand::Rng;
+use chrono::{DateTime, Local};
+use crate::shutdown::{DoneSignal, ShutdownRequest, wait_for};
+use crate::http_api::ServiceRegistry;
+use crate::http_client::ClientPool;
+use crate::events::EventLog;
+use json::{object, JsonValue};
 
+/// A bookable category of a provider, e.g. one vaccine at one site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calendar {
+    pub id: u64,
+    pub name: String,
+    /// Page where this calendar can be booked
+    pub booking_url: Option<String>
+}
+
+/// Calendars whose availability was found by a poll.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PollReport {
+    /// Free now, but not at the previous poll
+    pub added: Vec<Calendar>,
+    pub all_free: Vec<Calendar>,
+    /// Free at the previous poll, but not anymore
+    pub removed: Vec<Calendar>,
+    /// One of the newly freed calendars is marked as important by the provider settings
+    pub important: bool
+}
+
+impl PollReport {
+    fn names(calendars: &[Calendar]) -> Vec<String> {
+        calendars.iter().map(|calendar| calendar.name.clone()).collect()
+    }
+
+    /// Priority from 0 to 10 within the severity, rising with the number of newly freed calendars.
+    ///
+    /// Urgent reports start at 7 and reach 9, important ones get 10. Normal
+    /// reports stay between 1 and 4.
+    fn priority(&self, severity: Severity) -> u8 {
+        let added = self.added.len().min(u8::MAX as usize) as u8;
+        match severity {
+            Severity::Urgent if self.important => MAX_PRIORITY,
+            Severity::Urgent => 7u8.saturating_add(added.saturating_sub(1)).min(9),
+            Severity::Normal => 1u8.saturating_add(added).min(URGENT_PRIORITY - 1)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum PollResult {
     None,
-    Normal(String),
-    Urgent(String)
+    Normal(PollReport),
+    Urgent(PollReport)
+}
+
+/// Result of a poll triggered by [`ServiceHandle::poll_now`].
+#[derive(Debug, Clone)]
+pub struct TriggeredPoll {
+    pub result: PollResult,
+    /// The message sent to the notifications, if any
+    pub message: Option<String>
 }
 
+/// Source of appointment information, polled by a [`Service`].
+///
+/// A provider is polled by one thread at a time. If a poll is due while the
+/// previous one is still running, e.g. in the thread of a service replaced by
+/// a reload, the due poll is skipped and logged instead of queued.
 pub trait ServiceProvider: Debug + Send + Sync {
-    fn poll_once(&mut self) -> Result<PollResult, Box<dyn Error>>;
+    fn poll_once(&mut self) -> Result<PollResult, AppError>;
+
+    /// Writes the message about a poll report, as markdown.
+    ///
+    /// `severity` is the one the message is sent with, e.g. to add a headline to urgent ones.
+    fn render(&self, report: &PollReport, severity: Severity) -> String;
+
+    /// Performs a test request without changing the provider's state.
+    fn self_check(&mut self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// Page where the polled appointments can be booked, attached to notifications.
+    fn url(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Sends one test request per enabled service and reports failures to the admins.
+///
+/// Returns `true` if all services are reachable.
+pub fn self_check(config: &Config, clients: &ClientPool, admin_notif: &AdminNotificationsSender) -> bool {
+    let mut ok = true;
+    for settings in config.services.iter().filter(|settings| settings.enabled) {
+        let provider = ServiceCollection::create_provider(settings, clients);
+        let result = provider.lock().unwrap().self_check();
+        match result {
+            Ok(_) => info!("Self-check of service {} passed", settings.title),
+            Err(error) => {
+                ok = false;
+                let msg = format!("Self-check failed: {}", error);
+                error!("{}: {}", settings.title, msg);
+                admin_notif.send(settings.title.as_str(), msg.as_str());
+            }
+        }
+    }
+    ok
 }
 
+/// Decides when a service polls next.
 #[derive(Debug)]
-pub struct Service {
-    thrd: thread::JoinHandle<()>,
-    kill_tx: mpsc::Sender<bool>
+pub enum PollSchedule {
+    /// Poll immediately, then every `sleep ± jitter` seconds.
+    Interval { sleep: u32, jitter: u32 },
+    /// Like `Interval`, but `sleep` drops to `min` when free appointments show
+    /// up and grows by `factor` after every poll without new ones, up to `max`.
+    Adaptive { sleep: u32, jitter: u32, min: u32, max: u32, factor: f64 },
+    /// Poll at the times matched by a cron expression (local time).
+    Cron(Box<cron::Schedule>),
+    /// Poll a single time, then stop.
+    Once
 }
 
-impl Service {
-    pub fn new(title: String, provider: Arc<Mutex<dyn ServiceProvider>>, notifications: NotificatorSubCollection, sleep: u32, admin_notif: AdminNotificationsSender) -> Service {
-        let (kill_tx, kill_rx) = mpsc::channel();
-        let thrd = thread::spawn(move || {
-            let mut running = true;
-            while running {
-                let mut locked_provider = provider.lock().unwrap();
-
-                info!("Polling {}", title);
-                match locked_provider.poll_once() {
-                    Ok(result) => match result {
-                        PollResult::Urgent(msg) => match notifications.send_urgent(title.as_str(), msg.as_str()) {
-                            Ok(_) => (),
-                            Err(error) => {
-                                error!("{}: {}", title.as_str(), error.to_string().as_str());
-                                admin_notif.send(title.as_str(), error.to_string().as_str())
-                            }
-                        },
-                        PollResult::Normal(msg) => match notifications.send_normal(title.as_str(), msg.as_str()) {
-                            Ok(_) => (),
-                            Err(error) => {
-                                error!("{}: {}", title.as_str(), error.to_string().as_str());
-                                admin_notif.send(title.as_str(), error.to_string().as_str())
-                            }
-                        },
-                        PollResult::None => ()
-                    },
-                    Err(error) => {
-                        error!("{}: {}", title.as_str(), error.to_string().as_str());
-                        admin_notif.send(title.as_str(), error.to_string().as_str())
+impl PollSchedule {
+    pub fn from(settings: &ServiceSettings) -> PollSchedule {
+        match (&settings.cron, &settings.adaptive) {
+            (Some(schedule), _) => PollSchedule::Cron(Box::new(schedule.clone())),
+            (None, Some(adaptive)) => PollSchedule::Adaptive{
+                sleep: settings.sleep.clamp(adaptive.min_sleep, adaptive.max_sleep),
+                jitter: settings.jitter_secs,
+                min: adaptive.min_sleep,
+                max: adaptive.max_sleep,
+                factor: adaptive.factor
+            },
+            (None, None) => PollSchedule::Interval{
+                sleep: settings.sleep,
+                jitter: settings.jitter_secs
+            }
+        }
+    }
+
+    /// Adapts the interval of an adaptive schedule to the result of a poll.
+    fn record(&mut self, result: &PollResult) {
+        if let PollSchedule::Adaptive{sleep, min, max, factor, ..} = self {
+            *sleep = match result {
+                PollResult::Urgent(_) => *min,
+                PollResult::Normal(_) | PollResult::None => ((*sleep as f64 * *factor).ceil() as u32).min(*max)
+            };
+        }
+    }
+
+    fn first_delay(&self) -> Option<Duration> {
+        match self {
+            PollSchedule::Interval{..} | PollSchedule::Adaptive{..} | PollSchedule::Once => Some(Duration::from_secs(0)),
+            PollSchedule::Cron(_) => self.next_delay()
+        }
+    }
+
+    /// Time until the next poll, or `None` if no further poll is scheduled.
+    fn next_delay(&self) -> Option<Duration> {
+        match self {
+            PollSchedule::Interval{sleep, jitter} | PollSchedule::Adaptive{sleep, jitter, ..} => Some(Duration::from_secs(Self::jittered_sleep(*sleep, *jitter))),
+            PollSchedule::Cron(schedule) => schedule.upcoming(Local).next()
+                .map(|next| (next - Local::now()).to_std().unwrap_or_else(|_| Duration::from_secs(0))),
+            PollSchedule::Once => None
+        }
+    }
+
+    /// Randomizes the sleep interval to `sleep ± jitter` seconds.
+    fn jittered_sleep(sleep: u32, jitter: u32) -> u64 {
+        if jitter == 0 {
+            return sleep as u64;
+        }
+        let offset = rand::thread_rng().gen_range(-(jitter as i64)..=(jitter as i64));
+        (sleep as i64 + offset).max(0) as u64
+    }
+}
+
+/// Longest time a poll including its notifications may take before the service counts as stuck.
+const POLL_TIMEOUT: Duration = Duration::from_secs(600);
+/// Tolerance for a service waking up late from its sleep.
+const SLEEP_GRACE: Duration = Duration::from_secs(60);
+
+/// Minimum time between two restarts of a crashed service.
+const RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Pauses a service which keeps failing, e.g. because its server was shut down.
+///
+/// After `max_failures` failed polls in a row the breaker is open and the
+/// service only tries again after `reset`. A successful poll closes it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    max_failures: u32,
+    reset: Duration,
+    failures: u32
+}
+
+impl CircuitBreaker {
+    pub fn from(settings: &ServiceSettings) -> Option<CircuitBreaker> {
+        settings.max_consecutive_failures.map(|max_failures| CircuitBreaker{
+            max_failures,
+            reset: Duration::from_secs(settings.circuit_reset_secs as u64),
+            failures: 0
+        })
+    }
+
+    fn is_open(&self) -> bool {
+        self.failures >= self.max_failures
+    }
+
+    /// Counts a poll. Returns `true` if this failure opened the breaker.
+    fn record(&mut self, ok: bool) -> bool {
+        if ok {
+            self.failures = 0;
+            false
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            self.failures == self.max_failures
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LivenessState {
+    deadline: Instant,
+    running: bool,
+    crashed: bool,
+    last_poll: Option<LastPoll>,
+    circuit_open: bool
+}
+
+/// Time and outcome of the most recent poll of a service.
+#[derive(Debug, Clone, Copy)]
+pub struct LastPoll {
+    pub time: DateTime<Local>,
+    pub ok: bool
+}
+
+/// Tells whether a service thread is still running and making progress.
+///
+/// The service announces when it expects to report back next. It counts as
+/// dead once it has stopped or missed that deadline.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    state: Arc<Mutex<LivenessState>>
+}
+
+impl Liveness {
+    fn new() -> Liveness {
+        Liveness{
+            state: Arc::new(Mutex::new(LivenessState{
+                deadline: Instant::now() + POLL_TIMEOUT,
+                running: true,
+                crashed: false,
+                last_poll: None,
+                circuit_open: false
+            }))
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LivenessState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    fn expect_within(&self, duration: Duration) {
+        self.lock().deadline = Instant::now() + duration;
+    }
+
+    fn stopped(&self, crashed: bool) {
+        let mut state = self.lock();
+        state.running = false;
+        state.crashed = crashed;
+    }
+
+    fn polled(&self, ok: bool, circuit_open: bool) {
+        let mut state = self.lock();
+        state.last_poll = Some(LastPoll{time: Local::now(), ok});
+        state.circuit_open = circuit_open;
+    }
+
+    /// Returns `true` while the circuit breaker of the service pauses its polls.
+    pub fn is_circuit_open(&self) -> bool {
+        self.lock().circuit_open
+    }
+
+    fn is_running(&self) -> bool {
+        self.lock().running
+    }
+
+    pub fn is_alive(&self) -> bool {
+        let state = self.lock();
+        state.running && Instant::now() <= state.deadline
+    }
+
+    pub fn last_poll(&self) -> Option<LastPoll> {
+        self.lock().last_poll
+    }
+
+    /// Returns `true` if the service thread ended with a panic.
+    pub fn has_crashed(&self) -> bool {
+        self.lock().crashed
+    }
+}
+
+/// Marks the service as stopped when its thread ends, also by a panic.
+struct StoppedSignal(Liveness);
+
+impl Drop for StoppedSignal {
+    fn drop(&mut self) {
+        self.0.stopped(thread::panicking());
+    }
+}
+
+/// Drops normal messages with the same text as an urgent one of the same poll.
+///
+/// The recipients are told once, with the higher severity.
+fn deduplicate(messages: Vec<Notification>) -> Vec<Notification> {
+    let urgent: Vec<String> = messages.iter()
+        .filter(|message| message.severity == Severity::Urgent)
+        .map(|message| message.body.clone())
+        .collect();
+    messages.into_iter()
+        .filter(|message| message.severity == Severity::Urgent || !urgent.contains(&message.body))
+        .collect()
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Normal => "normal",
+        Severity::Urgent => "urgent"
+    }
+}
+
+/// Lists the IDs of the changed calendars for the events file.
+fn report_to_event(result: &str, report: &PollReport) -> JsonValue {
+    let ids = |calendars: &[Calendar]| -> JsonValue {
+        calendars.iter().map(|calendar| calendar.id).collect::<Vec<u64>>().into()
+    };
+    object!{
+        "result" => result,
+        "added" => ids(&report.added),
+        "removed" => ids(&report.removed),
+        "all_free" => ids(&report.all_free)
+    }
+}
+
+/// Requests to a running service.
+#[derive(Debug)]
+pub enum ServiceCommand {
+    /// Stop after the current poll
+    Kill,
+    /// Poll immediately and send the result back
+    PollNow(mpsc::Sender<Result<TriggeredPoll, String>>)
+}
+
+/// Sends commands to a service and wakes the shared scheduler if it polls the service.
+#[derive(Debug, Clone)]
+struct CommandSender {
+    command_tx: mpsc::Sender<ServiceCommand>,
+    wake_tx: Option<mpsc::Sender<SchedulerMessage>>
+}
+
+impl CommandSender {
+    /// Returns `false` if the service has stopped.
+    fn send(&self, command: ServiceCommand) -> bool {
+        if self.command_tx.send(command).is_err() {
+            return false;
+        }
+        if let Some(wake_tx) = &self.wake_tx {
+            let _ = wake_tx.send(SchedulerMessage::Wake);
+        }
+        true
+    }
+}
+
+/// Lets other threads observe and control a running service.
+#[derive(Debug, Clone)]
+pub struct ServiceHandle {
+    liveness: Liveness,
+    commands: CommandSender
+}
+
+impl ServiceHandle {
+    pub fn is_alive(&self) -> bool {
+        self.liveness.is_alive()
+    }
+
+    pub fn last_poll(&self) -> Option<LastPoll> {
+        self.liveness.last_poll()
+    }
+
+    /// Triggers a poll outside the schedule and waits for its result.
+    pub fn poll_now(&self) -> Result<TriggeredPoll, String> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        if !self.commands.send(ServiceCommand::PollNow(reply_tx)) {
+            return Err(String::from("Service is not running"));
+        }
+        // A scheduled poll may be running already, so wait for up to two polls
+        match reply_rx.recv_timeout(POLL_TIMEOUT * 2) {
+            Ok(result) => result,
+            Err(_) => Err(String::from("Service did not answer"))
+        }
+    }
+}
+
+/// Everything a service needs for its polls, owned by the thread polling it.
+struct Worker {
+    title: String,
+    tags: Vec<String>,
+    provider: Arc<Mutex<dyn ServiceProvider>>,
+    notifications: NotificatorSubCollection,
+    schedule: PollSchedule,
+    breaker: Option<CircuitBreaker>,
+    admin_notif: AdminNotificationsSender,
+    events: EventLog,
+    /// Added once to the delay of the first poll
+    start_offset: Duration
+}
+
+impl Worker {
+    fn notification(title: &str, provider: &dyn ServiceProvider, report: &PollReport, severity: Severity) -> Notification {
+        let text = provider.render(report, severity);
+        info!("{}", text);
+        Notification{
+            added: PollReport::names(&report.added),
+            removed: PollReport::names(&report.removed),
+            important: report.important,
+            booking_urls: report.added.iter()
+                .filter_map(|calendar| calendar.booking_url.clone())
+                .fold(Vec::new(), |mut urls, url| {
+                    if !urls.contains(&url) {
+                        urls.push(url);
                     }
+                    urls
+                }),
+            ..Notification::new(title, text.as_str(), severity)
+        }.with_priority(report.priority(severity))
+    }
+
+    fn first_delay(&self) -> Option<Duration> {
+        self.schedule.first_delay().map(|delay| delay + self.start_offset)
+    }
+
+    /// Polls once, sends the notifications and returns the time until the next poll.
+    ///
+    /// `reply_tx` receives the result of a triggered poll.
+    fn poll(&mut self, liveness: &Liveness, reply_tx: Option<mpsc::Sender<Result<TriggeredPoll, String>>>) -> Option<Duration> {
+        let Worker{title, tags, provider, notifications, schedule, breaker, admin_notif, events, ..} = self;
+        liveness.expect_within(POLL_TIMEOUT);
+
+        let mut locked_provider = match provider.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => {
+                let msg = "Provider panicked in a previous poll. Continuing with its last state.";
+                warn!("{}: {}", title, msg);
+                admin_notif.send(title.as_str(), msg);
+                provider.clear_poison();
+                poisoned.into_inner()
+            },
+            // E.g. the thread replaced by a reload is still polling. Queueing would pile up requests.
+            Err(TryLockError::WouldBlock) => {
+                let msg = "Previous poll is still running. Skipping this poll.";
+                warn!("{}: {}", title, msg);
+                if let Some(reply_tx) = reply_tx {
+                    let _ = reply_tx.send(Err(String::from(msg)));
                 }
+                return schedule.next_delay();
+            }
+        };
 
-                info!("Sleeping. Next poll of {} in {} s.", title, sleep);
-                'sleep: for _index in 0..sleep {
-                    thread::sleep(Duration::from_secs(1));
-                    match kill_rx.try_recv() {
-                        Ok(_) => {
-                            running = false;
-                            break 'sleep;
-                        },
-                        Err(_) => ()
+        info!("Polling {}", title);
+        events.emit("poll_started", title, object!{});
+        // A panicking provider must not take the service thread down with it
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| locked_provider.poll_once())) {
+            Ok(result) => result,
+            Err(_) => Err(AppError::Poll(String::from("Provider panicked during poll")))
+        };
+        if let Ok(result) = &result {
+            schedule.record(result);
+        }
+        let was_open = breaker.as_ref().is_some_and(|breaker| breaker.is_open());
+        let opened = match breaker.as_mut() {
+            Some(breaker) => breaker.record(result.is_ok()),
+            None => false
+        };
+        let is_open = breaker.as_ref().is_some_and(|breaker| breaker.is_open());
+        liveness.polled(result.is_ok(), is_open);
+        events.emit("poll_finished", title, match &result {
+            Ok(PollResult::None) => object!{"result" => "none"},
+            Ok(PollResult::Normal(report)) => report_to_event("normal", report),
+            Ok(PollResult::Urgent(report)) => report_to_event("urgent", report),
+            Err(error) => object!{"result" => "error", "error" => error.to_string()}
+        });
+        let url = locked_provider.url();
+        // Rendering happens here, so the provider only has to detect the changes
+        let messages = match &result {
+            Ok(PollResult::Urgent(report)) => vec![Self::notification(title, &*locked_provider, report, Severity::Urgent)],
+            Ok(PollResult::Normal(report)) => vec![Self::notification(title, &*locked_provider, report, Severity::Normal)],
+            Ok(PollResult::None) | Err(_) => Vec::new()
+        };
+        if let Some(reply_tx) = reply_tx {
+            // The requester may have given up waiting
+            let _ = reply_tx.send(match &result {
+                Ok(result) => Ok(TriggeredPoll{
+                    result: result.clone(),
+                    message: messages.first().map(|message| message.body.clone())
+                }),
+                Err(error) => Err(error.to_string())
+            });
+        }
+        match result {
+            Ok(_) => {
+                for message in deduplicate(messages) {
+                    let severity = severity_name(message.severity);
+                    match notifications.send(&message.with_url(url.clone()).with_tags(tags.clone())) {
+                        Ok(_) => events.emit("notification_sent", title, object!{"severity" => severity}),
+                        Err(error) => {
+                            events.emit("notification_failed", title, object!{"severity" => severity, "error" => error.to_string()});
+                            error!("{}: {}", title.as_str(), error.to_string().as_str());
+                            admin_notif.send(title.as_str(), error.to_string().as_str())
+                        }
                     }
                 }
+            },
+            // The admins were told when the breaker opened
+            Err(error) if was_open => warn!("{}: Still failing: {}", title.as_str(), error.to_string().as_str()),
+            Err(error) => {
+                error!("{}: {}", title.as_str(), error.to_string().as_str());
+                admin_notif.send(title.as_str(), error.to_string().as_str())
+            }
+        }
+
+        match breaker.as_ref() {
+            Some(breaker) if breaker.is_open() => {
+                if opened {
+                    let msg = format!("{} polls failed in a row. Pausing polls for {} s, or until the configuration is reloaded.",
+                                      breaker.max_failures, breaker.reset.as_secs());
+                    error!("{}: {}", title, msg);
+                    admin_notif.send(title.as_str(), msg.as_str());
+                }
+                schedule.next_delay().map(|_| breaker.reset)
+            },
+            _ => {
+                if was_open {
+                    let msg = "Polling works again.";
+                    info!("{}: {}", title, msg);
+                    admin_notif.send(title.as_str(), msg);
+                }
+                schedule.next_delay()
             }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Service {
+    /// `None` if the service is polled by the shared scheduler
+    thrd: Option<thread::JoinHandle<()>>,
+    commands: CommandSender,
+    liveness: Liveness
+}
+
+impl Service {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(title: String, tags: Vec<String>, provider: Arc<Mutex<dyn ServiceProvider>>, notifications: NotificatorSubCollection, schedule: PollSchedule, breaker: Option<CircuitBreaker>, admin_notif: AdminNotificationsSender, events: EventLog, done_tx: mpsc::Sender<()>) -> Service {
+        let worker = Worker{title, tags, provider, notifications, schedule, breaker, admin_notif, events, start_offset: Duration::ZERO};
+        Self::spawn(worker, done_tx, None)
+    }
+
+    /// Runs the worker in its own thread, or hands it to `scheduler`.
+    fn spawn(worker: Worker, done_tx: mpsc::Sender<()>, scheduler: Option<&Scheduler>) -> Service {
+        let (command_tx, command_rx) = mpsc::channel();
+        let liveness = Liveness::new();
+        if let Some(scheduler) = scheduler {
+            let task = Task{
+                due: worker.first_delay().map(|delay| {
+                    liveness.expect_within(delay + SLEEP_GRACE + POLL_TIMEOUT);
+                    Instant::now() + delay
+                }),
+                worker,
+                command_rx,
+                reply_tx: None,
+                liveness: liveness.clone(),
+                crashed: false,
+                _done: DoneSignal::new(done_tx)
+            };
+            let wake_tx = scheduler.add(task);
+            return Service{
+                thrd: None,
+                commands: CommandSender{command_tx, wake_tx: Some(wake_tx)},
+                liveness
+            };
+        }
+        let thread_liveness = liveness.clone();
+        let thrd = thread::spawn(move || {
+            let _done = DoneSignal::new(done_tx);
+            let liveness = StoppedSignal(thread_liveness);
+            let mut worker = worker;
+            let mut next = worker.first_delay();
+            while let Some(delay) = next {
+                if !delay.is_zero() {
+                    info!("Sleeping. Next poll of {} in {} s.", worker.title, delay.as_secs());
+                }
+                liveness.0.expect_within(delay + SLEEP_GRACE);
+                let reply_tx = match command_rx.recv_timeout(delay) {
+                    Ok(ServiceCommand::PollNow(reply_tx)) => {
+                        info!("Poll of {} was triggered", worker.title);
+                        Some(reply_tx)
+                    },
+                    Ok(ServiceCommand::Kill) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    Err(mpsc::RecvTimeoutError::Timeout) => None
+                };
+                next = worker.poll(&liveness.0, reply_tx);
+            }
+            if next.is_none() {
+                info!("No further polls of {} are scheduled.", worker.title);
+            }
+            info!("Service {} stopped.", worker.title);
         });
         Service{
-            thrd,
-            kill_tx
+            thrd: Some(thrd),
+            commands: CommandSender{command_tx, wake_tx: None},
+            liveness
+        }
+    }
+
+    pub fn handle(&self) -> ServiceHandle {
+        ServiceHandle{
+            liveness: self.liveness.clone(),
+            commands: self.commands.clone()
         }
     }
 
-    pub fn get_killer(&self) -> mpsc::Sender<bool> {
-        self.kill_tx.clone()
+    /// Asks the service to stop after its current poll.
+    pub fn kill(&self) {
+        // A service which has already stopped has dropped its receiver.
+        self.commands.send(ServiceCommand::Kill);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match &self.thrd {
+            Some(thrd) => thrd.is_finished(),
+            None => !self.liveness.is_running()
+        }
     }
 
     pub fn join(self) -> thread::Result<()> {
-        self.thrd.join()
+        match self.thrd {
+            Some(thrd) => thrd.join(),
+            None => Ok(())
+        }
+    }
+}
+
+/// A service polled by the shared scheduler.
+struct Task {
+    worker: Worker,
+    /// `None` once no further poll is scheduled
+    due: Option<Instant>,
+    command_rx: mpsc::Receiver<ServiceCommand>,
+    /// Requester of a triggered poll which has not run yet
+    reply_tx: Option<mpsc::Sender<Result<TriggeredPoll, String>>>,
+    liveness: Liveness,
+    crashed: bool,
+    _done: DoneSignal
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.liveness.stopped(self.crashed || thread::panicking());
+        info!("Service {} stopped.", self.worker.title);
     }
 }
 
+impl Task {
+    /// Takes the next command, returns `false` if the service has to stop.
+    fn receive_command(&mut self) -> bool {
+        if self.reply_tx.is_some() {
+            // The triggered poll runs first, like in a service thread
+            return true;
+        }
+        match self.command_rx.try_recv() {
+            Ok(ServiceCommand::PollNow(reply_tx)) => {
+                info!("Poll of {} was triggered", self.worker.title);
+                self.reply_tx = Some(reply_tx);
+                self.due = Some(Instant::now());
+                true
+            },
+            Ok(ServiceCommand::Kill) | Err(mpsc::TryRecvError::Disconnected) => false,
+            Err(mpsc::TryRecvError::Empty) => true
+        }
+    }
+
+    fn poll(&mut self) {
+        let reply_tx = self.reply_tx.take();
+        let delay = self.worker.poll(&self.liveness, reply_tx);
+        match delay {
+            Some(delay) => {
+                if !delay.is_zero() {
+                    info!("Sleeping. Next poll of {} in {} s.", self.worker.title, delay.as_secs());
+                }
+                // Another service may be polling when this one is due
+                self.liveness.expect_within(delay + SLEEP_GRACE + POLL_TIMEOUT);
+                self.due = Some(Instant::now() + delay);
+            },
+            None => {
+                info!("No further polls of {} are scheduled.", self.worker.title);
+                self.due = None;
+            }
+        }
+    }
+}
+
+enum SchedulerMessage {
+    Add(Box<Task>),
+    /// A command was sent to one of the services
+    Wake
+}
+
+impl Debug for SchedulerMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SchedulerMessage::Add(task) => write!(f, "Add({})", task.worker.title),
+            SchedulerMessage::Wake => write!(f, "Wake")
+        }
+    }
+}
+
+/// Polls all services in one thread, for hosts where a thread per service is too much.
+///
+/// The thread polls the service which is due next and sleeps until the next
+/// one is due. Services take turns, so a slow poll delays the others.
+#[derive(Debug)]
+struct Scheduler {
+    tx: mpsc::Sender<SchedulerMessage>
+}
+
+impl Scheduler {
+    fn start() -> Scheduler {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || Self::run(rx));
+        Scheduler{
+            tx
+        }
+    }
+
+    /// Hands a service to the scheduler thread and returns the sender to wake it up.
+    fn add(&self, task: Task) -> mpsc::Sender<SchedulerMessage> {
+        // The thread only ends once every sender is dropped
+        let _ = self.tx.send(SchedulerMessage::Add(Box::new(task)));
+        self.tx.clone()
+    }
+
+    fn run(rx: mpsc::Receiver<SchedulerMessage>) {
+        let mut tasks: Vec<Task> = Vec::new();
+        loop {
+            // Dropping a task marks its service as stopped
+            tasks.retain_mut(|task| task.receive_command() && task.due.is_some());
+
+            let now = Instant::now();
+            let next = tasks.iter_mut()
+                .filter(|task| task.due.is_some())
+                .min_by_key(|task| task.due);
+            let wait = match next {
+                Some(task) if task.due.is_some_and(|due| due <= now) => {
+                    // A panic must only stop the service which caused it
+                    if panic::catch_unwind(AssertUnwindSafe(|| task.poll())).is_err() {
+                        error!("Service {} panicked", task.worker.title);
+                        task.crashed = true;
+                        task.due = None;
+                    }
+                    continue;
+                },
+                Some(task) => task.due.map(|due| due - now),
+                None => None
+            };
+            let message = match wait {
+                Some(wait) => match rx.recv_timeout(wait) {
+                    Ok(message) => Some(message),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break
+                },
+                None => match rx.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break
+                }
+            };
+            if let Some(SchedulerMessage::Add(task)) = message {
+                tasks.push(*task);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RunningService {
+    settings: ServiceSettings,
+    provider: Arc<Mutex<dyn ServiceProvider>>,
+    service: Service
+}
+
 #[derive(Debug)]
 pub struct ServiceCollection {
-    services: Vec<Service>
+    services: HashMap<String, RunningService>,
+    /// Services stopped by a reload which may still be finishing their poll
+    retired: Vec<Service>,
+    done_tx: mpsc::Sender<()>,
+    done_rx: mpsc::Receiver<()>,
+    shutdown: ShutdownRequest,
+    once: bool,
+    registry: ServiceRegistry,
+    /// Time of the last restart of each crashed service
+    restarts: HashMap<String, Instant>,
+    clients: ClientPool,
+    events: EventLog,
+    /// Polls all services in one thread, `None` if every service has its own thread
+    scheduler: Option<Scheduler>
 }
 
 impl ServiceCollection {
-    fn new() -> Self {
+    fn new(once: bool, single_threaded: bool, clients: &ClientPool, events: &EventLog) -> Self {
+        let (done_tx, done_rx) = mpsc::channel();
         ServiceCollection{
-            services: Vec::new()
+            services: HashMap::new(),
+            retired: Vec::new(),
+            done_tx,
+            done_rx,
+            shutdown: ShutdownRequest::new(),
+            once,
+            registry: ServiceRegistry::default(),
+            restarts: HashMap::new(),
+            clients: clients.clone(),
+            events: events.clone(),
+            scheduler: if single_threaded {
+                Some(Scheduler::start())
+            } else {
+                None
+            }
         }
     }
 
-    fn add(&mut self, service: Service) {
-        self.services.push(service)
+    fn create_provider(settings: &ServiceSettings, clients: &ClientPool) -> Arc<Mutex<dyn ServiceProvider>> {
+        match &settings.provider {
+            ServiceProviderSettings::Booked4us(s) => Arc::new(Mutex::new(Booked4us::from(s, clients))),
+            ServiceProviderSettings::Doctolib(s) => Arc::new(Mutex::new(Doctolib::from(s, clients)))
+        }
     }
 
-    pub fn from(config: &Config, notificators: &NotificatorCollection, admin_notif: &AdminNotifications) -> Self {
-        let mut coll = ServiceCollection::new();
+    fn start(&mut self, settings: &ServiceSettings, provider: Arc<Mutex<dyn ServiceProvider>>, notificators: &NotificatorCollection, admin_notif: &AdminNotifications) {
+        let notifications = notificators.subcollection(&settings.notifications).fail_fast(settings.notify_fail_fast);
+        let schedule = if self.once {
+            PollSchedule::Once
+        } else {
+            PollSchedule::from(settings)
+        };
+        let worker = Worker{
+            title: settings.title.clone(),
+            tags: settings.tags.clone(),
+            provider: provider.clone(),
+            notifications,
+            schedule,
+            breaker: CircuitBreaker::from(settings),
+            admin_notif: admin_notif.get_tx(),
+            events: self.events.clone(),
+            // Polling once should not wait
+            start_offset: if self.once {
+                Duration::ZERO
+            } else {
+                Duration::from_secs(settings.start_offset_secs as u64)
+            }
+        };
+        let service = Service::spawn(worker, self.done_tx.clone(), self.scheduler.as_ref());
+        self.registry.insert(settings.title.as_str(), service.handle());
+        self.services.insert(settings.title.clone(), RunningService{
+            settings: settings.clone(),
+            provider,
+            service
+        });
+    }
+
+    /// Creates a service for each configured service provider.
+    ///
+    /// If `once` is set, every service polls a single time and stops instead
+    /// of following its schedule. With `single_threaded` in the config, all
+    /// services are polled by one thread instead of a thread each.
+    /// HTTP requests of the services use the shared clients of `clients`.
+    /// Polls, changes and notifications are written to `events`.
+    pub fn from(config: &Config, clients: &ClientPool, notificators: &NotificatorCollection, admin_notif: &AdminNotifications, events: &EventLog, once: bool) -> Self {
+        let mut coll = ServiceCollection::new(once, config.single_threaded, clients, events);
         for settings in config.services.iter() {
-            let provider = Arc::new(
-                Mutex::new(match &settings.provider {
-                    ServiceProviderSettings::Booked4us(s) => Booked4us::from(s)
-                })
-            );
-            let notifications = notificators.subcollection(&settings.notifications);
-            coll.add(Service::new(settings.title.clone(), provider, notifications, settings.sleep, admin_notif.get_tx()));
+            if settings.enabled {
+                let provider = Self::create_provider(settings, clients);
+                coll.start(settings, provider, notificators, admin_notif);
+            } else {
+                info!("Service {} is disabled", settings.title);
+            }
         }
         coll
     }
 
-    pub fn get_killers(&self) -> ServiceKillers {
-        ServiceKillers{
-            kill_tx: {
-                let mut v: Vec<mpsc::Sender<bool>> = Vec::new();
-                for srv in &self.services {
-                    v.push(srv.get_killer());
+    /// Applies a changed configuration to the running services.
+    ///
+    /// Removed and disabled services are stopped, new ones are started. A service is
+    /// restarted if its settings changed or if it uses one of the
+    /// `changed_notifications`. The provider, and with it the knowledge about
+    /// already announced appointments, is kept as long as the provider
+    /// settings are unchanged. All other services keep running untouched.
+    pub fn reload(&mut self, config: &Config, notificators: &NotificatorCollection, changed_notifications: &HashSet<String>, admin_notif: &AdminNotifications) {
+        let mut providers: HashMap<String, Arc<Mutex<dyn ServiceProvider>>> = HashMap::new();
+        let enabled: Vec<&ServiceSettings> = config.services.iter()
+            .filter(|settings| settings.enabled)
+            .collect();
+        for settings in enabled.iter().copied() {
+            if let Some(running) = self.services.get(&settings.title) {
+                let notifications_changed = settings.notifications.iter()
+                    .any(|name| changed_notifications.contains(name));
+                // A reload also resets an open circuit breaker
+                let circuit_open = running.service.liveness.is_circuit_open();
+                if running.settings == *settings && !notifications_changed && !circuit_open {
+                    continue;
                 }
-                v
             }
+            match self.services.remove(&settings.title) {
+                Some(running) => {
+                    info!("Restarting service {}", settings.title);
+                    running.service.kill();
+                    if running.settings.provider == settings.provider {
+                        providers.insert(settings.title.clone(), running.provider);
+                    }
+                    self.retired.push(running.service);
+                },
+                None => info!("Starting service {}", settings.title)
+            }
+            let provider = providers.remove(&settings.title)
+                .unwrap_or_else(|| Self::create_provider(settings, &self.clients));
+            self.start(settings, provider, notificators, admin_notif);
         }
+
+        let removed: Vec<String> = self.services.keys()
+            .filter(|title| !enabled.iter().any(|settings| &settings.title == *title))
+            .cloned()
+            .collect();
+        for title in removed {
+            info!("Stopping service {}", title);
+            self.registry.remove(title.as_str());
+            let running = self.services.remove(&title).unwrap();
+            running.service.kill();
+            self.retired.push(running.service);
+        }
+    }
+
+    pub(crate) fn registry(&self) -> ServiceRegistry {
+        self.registry.clone()
     }
 
-    pub fn join_all(mut self) {
-        while !self.services.is_empty() {
-            match self.services.pop() {
-                Some(srv) => srv.join().unwrap(),
-                None => ()
+    /// Restarts services whose thread died from a panic.
+    ///
+    /// The restarted service gets a fresh provider, so the next poll reports
+    /// all free appointments again. A service is restarted at most once per
+    /// `RESTART_DELAY` to avoid a crash loop hammering the polled server.
+    pub fn restart_crashed(&mut self, notificators: &NotificatorCollection, admin_notif: &AdminNotifications) {
+        let crashed: Vec<String> = self.services.iter()
+            .filter(|(_, running)| running.service.liveness.has_crashed())
+            .filter(|(title, _)| match self.restarts.get(*title) {
+                Some(restarted) => restarted.elapsed() >= RESTART_DELAY,
+                None => true
+            })
+            .map(|(title, _)| title.clone())
+            .collect();
+        for title in crashed {
+            let running = self.services.remove(&title).unwrap();
+            let msg = "Service thread crashed. Restarting it with a fresh state.";
+            error!("{}: {}", title, msg);
+            admin_notif.get_tx().send(title.as_str(), msg);
+            self.retired.push(running.service);
+            self.restarts.insert(title, Instant::now());
+            let provider = Self::create_provider(&running.settings, &self.clients);
+            self.start(&running.settings, provider, notificators, admin_notif);
+        }
+    }
+
+    /// Returns `true` once no service has a further poll scheduled.
+    ///
+    /// Crashed services are still waiting for their restart.
+    pub fn all_finished(&self) -> bool {
+        self.services.values().all(|running| running.service.is_finished() && !running.service.liveness.has_crashed())
+    }
+
+    /// Asks all services to stop after their current poll.
+    pub fn kill_all(&self) {
+        self.shutdown.request();
+        for running in self.services.values() {
+            running.service.kill();
+        }
+    }
+
+    /// Waits for all services to finish their current poll and stop.
+    ///
+    /// Services that are still busy `timeout` after they were killed are
+    /// abandoned, so a stuck provider cannot block the shutdown forever.
+    pub fn join_all(mut self, timeout: Duration) {
+        let count = self.services.len() + self.retired.len();
+        if !wait_for(&self.done_rx, count, &self.shutdown, timeout) {
+            warn!("Services did not stop within {} s. Abandoning them.", timeout.as_secs());
+            return;
+        }
+        let services = self.services.drain().map(|(_, running)| running.service);
+        for srv in services.chain(self.retired.drain(..)) {
+            if srv.join().is_err() {
+                error!("Service thread panicked");
             }
         }
     }
 }
 
-pub struct ServiceKillers {
-    kill_tx: Vec<mpsc::Sender<bool>>
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AdminRecipients;
+    use crate::notification::recording::{RecordingNotificator, subcollection};
 
-impl ServiceKillers {
-    pub fn kill_all(&self) {
-        for tx in &self.kill_tx {
-            tx.send(true).unwrap();
-        }
-    }
-}
-
-// #[derive(Debug)]
-// pub struct PollError {
-//     msg: String
-// }
-//
-// impl Error for PollError {}
-//
-// impl Display for PollError {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         write!(f, "Config parsing error: {}", self.msg)
-//     }
-// }
-//
-// impl PollError {
-//     fn new(s: &str) -> Box<Self> {
-//         Box::new(Self{msg: String::from(s)})
-//     }
-// }
+    /// Returns the same result on every poll.
+    #[derive(Debug)]
+    struct FixedProvider(PollResult);
+
+    impl ServiceProvider for FixedProvider {
+        fn poll_once(&mut self) -> Result<PollResult, AppError> {
+            Ok(self.0.clone())
+        }
+
+        fn render(&self, report: &PollReport, _severity: Severity) -> String {
+            format!("{} free", report.all_free.len())
+        }
+    }
+
+    /// Runs a service polling `result` once and returns the messages it sent.
+    fn dispatch(result: PollResult) -> Vec<(Severity, String, String)> {
+        let recorder = RecordingNotificator::new();
+        let admin = AdminNotifications::new(AdminRecipients{errors: subcollection(&[]), lifecycle: subcollection(&[])}, "Admin");
+        let (done_tx, _done_rx) = mpsc::channel();
+        let provider = Arc::new(Mutex::new(FixedProvider(result)));
+        Service::new(String::from("T"), Vec::new(), provider, subcollection(std::slice::from_ref(&recorder)), PollSchedule::Once, None, admin.get_tx(), EventLog::disabled(), done_tx)
+            .join()
+            .unwrap();
+        let sent = recorder.lock().unwrap().sent();
+        sent
+    }
+
+    #[test]
+    fn poll_results_are_sent_with_their_severity() {
+        let calendar = Calendar{id: 1, name: String::from("BioNTech"), booking_url: None};
+        let report = PollReport{added: vec![calendar.clone()], all_free: vec![calendar], ..PollReport::default()};
+
+        assert_eq!(dispatch(PollResult::Urgent(report.clone())), vec![(Severity::Urgent, String::from("T"), String::from("1 free"))]);
+        assert_eq!(dispatch(PollResult::Normal(report)), vec![(Severity::Normal, String::from("T"), String::from("1 free"))]);
+        assert!(dispatch(PollResult::None).is_empty());
+    }
+
+    #[test]
+    fn priority_rises_with_the_freed_calendars() {
+        let calendars: Vec<Calendar> = (1..=4).map(|id| Calendar{id, name: id.to_string(), booking_url: None}).collect();
+        let report = |added: usize, important: bool| PollReport{added: calendars[..added].to_vec(), important, ..PollReport::default()};
+
+        assert_eq!(report(1, false).priority(Severity::Urgent), 7);
+        assert_eq!(report(2, false).priority(Severity::Urgent), 8);
+        assert_eq!(report(4, false).priority(Severity::Urgent), 9);
+        assert_eq!(report(1, true).priority(Severity::Urgent), 10);
+        assert_eq!(report(0, false).priority(Severity::Normal), 1);
+        assert_eq!(report(4, false).priority(Severity::Normal), 4);
+    }
+
+    #[test]
+    fn shared_scheduler_polls_every_service() {
+        let recorder = RecordingNotificator::new();
+        let admin = AdminNotifications::new(AdminRecipients{errors: subcollection(&[]), lifecycle: subcollection(&[])}, "Admin");
+        let (done_tx, done_rx) = mpsc::channel();
+        let scheduler = Scheduler::start();
+        let report = PollReport::default();
+        let services: Vec<Service> = ["A", "B"].iter()
+            .map(|title| Worker{
+                title: String::from(*title),
+                tags: Vec::new(),
+                provider: Arc::new(Mutex::new(FixedProvider(PollResult::Normal(report.clone())))),
+                notifications: subcollection(std::slice::from_ref(&recorder)),
+                schedule: PollSchedule::Once,
+                breaker: None,
+                admin_notif: admin.get_tx(),
+                events: EventLog::disabled(),
+                start_offset: Duration::ZERO
+            })
+            .map(|worker| Service::spawn(worker, done_tx.clone(), Some(&scheduler)))
+            .collect();
+
+        for _ in 0..2 {
+            done_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+        assert!(services.iter().all(|service| service.is_finished()));
+        let titles: Vec<String> = recorder.lock().unwrap().sent().into_iter().map(|(_, title, _)| title).collect();
+        assert_eq!(titles, vec![String::from("A"), String::from("B")]);
+    }
+
+    #[test]
+    fn adaptive_interval_grows_and_resets_on_availability() {
+        let mut schedule = PollSchedule::Adaptive{sleep: 60, jitter: 0, min: 30, max: 100, factor: 1.5};
+        schedule.record(&PollResult::None);
+        assert_eq!(schedule.next_delay(), Some(Duration::from_secs(90)));
+        schedule.record(&PollResult::None);
+        assert_eq!(schedule.next_delay(), Some(Duration::from_secs(100)));
+        schedule.record(&PollResult::Urgent(PollReport::default()));
+        assert_eq!(schedule.next_delay(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn normal_duplicate_of_urgent_message_is_dropped() {
+        let messages = deduplicate(vec![
+            Notification::new("T", "free", Severity::Normal),
+            Notification::new("T", "free", Severity::Urgent),
+            Notification::new("T", "other", Severity::Normal)
+        ]);
+        assert_eq!(messages, vec![
+            Notification::new("T", "free", Severity::Urgent),
+            Notification::new("T", "other", Severity::Normal)
+        ]);
+    }
+}
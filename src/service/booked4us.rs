@@ -6,62 +6,157 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::error::Error;
+use crate::error::AppError;
 use std::fmt::Debug;
-use crate::service::{ServiceProvider, PollResult};
-use crate::config::Booked4usSettings;
-use reqwest;
-use json;
+use crate::service::{ServiceProvider, PollResult, PollReport, Calendar};
+use crate::notification::Severity;
+use crate::config::{Booked4usSettings, HttpAuth, LoginSettings, MessageMode};
 use json::{JsonValue};
 use crate::json_helper;
+use crate::i18n::Language;
+use crate::http_client::{ClientPool, status_error};
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT};
+use reqwest::StatusCode;
 use std::collections::{HashSet, HashMap};
-use log::{info};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use log::{info, warn};
+
+/// Pause before fetching a response again which was no valid JSON
+const PARSE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Calendar IDs are only unique per server, so they are namespaced by its URL.
+type CalendarKey = (String, u64);
 
 #[derive(Debug)]
 pub struct Booked4us {
-    url: String,
+    /// Servers whose calendars are merged, e.g. the sites of one city
+    urls: Vec<String>,
+    api_base_path: String,
     client: reqwest::Client,
-    free_ids: HashSet<u32>,
-    details: HashMap<u32, Detail>,
+    /// Sent with every request, as the client is shared
+    headers: HeaderMap,
+    auth: Option<HttpAuth>,
+    login: Option<LoginSettings>,
+    /// Whether the client holds a session cookie, cleared when the server rejects it
+    logged_in: AtomicBool,
+    message_mode: MessageMode,
+    message_template: Option<String>,
+    language: Language,
+    booking_url_template: Option<String>,
+    parse_retries: u32,
+    /// Number of newly freed calendars needed for an urgent message
+    min_added: u32,
+    /// Calendars which are no longer free cause an urgent message, too
+    notify_on_removed: bool,
+    watch_ids: Option<HashSet<u64>>,
+    important_ids: HashSet<u64>,
+    urgent_cooldown: Duration,
+    inter_request_delay: Duration,
+    silent_first_poll: bool,
+    /// Whether a poll has succeeded yet, which sets the baseline of a silent first poll
+    warmed_up: bool,
+    /// When each calendar last caused an urgent message
+    last_urgent: HashMap<CalendarKey, Instant>,
+    free_ids: HashSet<CalendarKey>,
+    details: HashMap<CalendarKey, Detail>,
+    /// Last calendar overview of each server, reused while the server reports it as unchanged
+    overview_cache: HashMap<String, CachedOverview>,
 }
 
 impl Booked4us {
-    pub fn from(settings: &Booked4usSettings) -> Booked4us {
+    pub fn from(settings: &Booked4usSettings, clients: &ClientPool) -> Booked4us {
+        let mut headers = settings.headers.clone();
+        if let Some(user_agent) = &settings.user_agent {
+            headers.insert(USER_AGENT, HeaderValue::from_str(user_agent).expect("User agent is validated when loading the config"));
+        }
+        // A session cookie must not be shared with the other services
+        let client = match &settings.login {
+            Some(_) => clients.session(&settings.urls.join(", "), &settings.proxy, &settings.tls, &settings.network),
+            None => clients.get(&settings.urls.join(", "), &settings.proxy, &settings.tls, &settings.network)
+        };
         Booked4us {
-            url: settings.url.clone(),
-            client: reqwest::Client::new(),
+            urls: settings.urls.clone(),
+            api_base_path: settings.api_base_path.clone(),
+            client,
+            headers,
+            auth: settings.auth.clone(),
+            login: settings.login.clone(),
+            logged_in: AtomicBool::new(false),
+            message_mode: settings.message_mode,
+            message_template: settings.message_template.clone(),
+            language: settings.language,
+            booking_url_template: settings.booking_url_template.clone(),
+            parse_retries: settings.parse_retries,
+            min_added: settings.min_added,
+            notify_on_removed: settings.notify_on_removed,
+            watch_ids: settings.watch_ids.clone(),
+            important_ids: settings.important_ids.clone(),
+            urgent_cooldown: Duration::from_secs(settings.urgent_cooldown_secs as u64),
+            inter_request_delay: Duration::from_millis(settings.inter_request_delay_ms as u64),
+            silent_first_poll: settings.silent_first_poll,
+            warmed_up: false,
+            last_urgent: HashMap::new(),
             free_ids: HashSet::new(),
             details: HashMap::new(),
+            overview_cache: HashMap::new(),
         }
     }
 
-    async fn async_poll(&mut self) -> Result<PollResult, Box<dyn Error>> {
-        let details = self.get_overview().await?;
+    async fn async_poll(&mut self) -> Result<PollResult, AppError> {
+        let mut details = self.get_overview().await?;
+        // Dropping the other calendars early also saves their free slot requests
+        if let Some(watch_ids) = &self.watch_ids {
+            details.retain(|(_, id), _| watch_ids.contains(id));
+        }
         info!("Details: {:?}", details);
         let free_slots = self.extract_free_slots(&details).await?;
         info!("Free Slots: {:?}", free_slots);
         let free_set = Self::map_to_set(&free_slots);
+        if self.silent_first_poll && !self.warmed_up {
+            info!("First poll found {} free calendars. Not notifying about them.", free_set.len());
+            self.free_ids = free_set;
+            self.details = details;
+            self.warmed_up = true;
+            return Ok(PollResult::None);
+        }
+        self.warmed_up = true;
         let res = if self.has_changed(&free_set) {
             info!("Free Slots have changed.");
             let added = self.extract_added_slots(&free_slots);
             let removed = self.extract_removed_slots(&free_set);
-
-            let text = format!(
-                "Frei gewordene Kategorien:\n{}\nAlle freien Kategorien:\n{}\nNicht mehr frei:\n{}\nURL: {}\n",
-                Self::vec_to_markdown(&added),
-                Self::vec_to_markdown(&Self::map_to_vec(&free_slots)),
-                Self::vec_to_markdown(&removed),
-                self.url
-            );
-            info!("{}", text);
+            let important = added.iter().any(|detail| self.important_ids.contains(&detail.id));
+            let report = PollReport{
+                added: self.to_calendars(&added),
+                all_free: self.to_calendars(&Self::map_to_vec(&free_slots)),
+                removed: self.to_calendars(&removed),
+                important
+            };
 
             self.free_ids = free_set.clone();
             self.details = details.clone();
 
-            if added.is_empty() {
-                PollResult::Normal(text)
+            // Calendars which caused an urgent message recently only count as normal
+            let fresh: Vec<CalendarKey> = added.iter()
+                .map(|detail| detail.key())
+                .filter(|key| match self.last_urgent.get(key) {
+                    Some(sent) => sent.elapsed() >= self.urgent_cooldown,
+                    None => true
+                })
+                .collect();
+            // Important calendars skip the cooldown and min_added
+            if important || (!fresh.is_empty() && fresh.len() >= self.min_added as usize) {
+                let now = Instant::now();
+                for detail in added.iter() {
+                    self.last_urgent.insert(detail.key(), now);
+                }
+                PollResult::Urgent(report)
+            } else if self.notify_on_removed && !removed.is_empty() {
+                PollResult::Urgent(report)
+            } else if added.is_empty() && self.message_mode == MessageMode::AddedOnly {
+                PollResult::None
             } else {
-                PollResult::Urgent(text)
+                PollResult::Normal(report)
             }
         } else {
             PollResult::None
@@ -70,126 +165,708 @@ impl Booked4us {
         Ok(res)
     }
 
-    async fn get_overview_json(&self) -> Result<JsonValue, Box<dyn Error>> {
-        let uri = format!("{}/rest-v2/api/Calendars/WithDetails", self.url);
-        let resp = self.client.get(&uri).send().await?;
-        let json_str = resp.text().await?;
-        let obj = json::parse(&json_str)?;
-        Ok(obj)
+    /// Renders the message from the configured template, or by the message mode.
+    ///
+    /// Templates may contain the placeholders `{added}`, `{all_free}`, `{removed}`,
+    /// `{added_count}`, `{all_free_count}`, `{removed_count}` and `{url}`.
+    fn render_message(&self, report: &PollReport) -> String {
+        let (added, all_free, removed) = (&report.added, &report.all_free, &report.removed);
+        if let Some(template) = &self.message_template {
+            return template
+                .replace("{added}", &self.vec_to_markdown(added))
+                .replace("{all_free}", &self.vec_to_markdown(all_free))
+                .replace("{removed}", &self.vec_to_markdown(removed))
+                .replace("{added_count}", &added.len().to_string())
+                .replace("{all_free_count}", &all_free.len().to_string())
+                .replace("{removed_count}", &removed.len().to_string())
+                .replace("{url}", &self.urls.join(", "));
+        }
+        let texts = self.language.texts();
+        match self.message_mode {
+            MessageMode::Full => format!(
+                "{}\n{}\n{}\n{}\n{}\n{}\nURL: {}\n",
+                texts.added,
+                self.vec_to_markdown(added),
+                texts.all_free,
+                self.vec_to_markdown(all_free),
+                texts.removed,
+                self.vec_to_markdown(removed),
+                self.urls.join(", ")
+            ),
+            // Removals are only part of the message if they are announced
+            MessageMode::AddedOnly if self.notify_on_removed && !removed.is_empty() => format!(
+                "{}\n{}\n{}\n{}\nURL: {}\n",
+                texts.added,
+                self.vec_to_markdown(added),
+                texts.removed,
+                self.vec_to_markdown(removed),
+                self.urls.join(", ")
+            ),
+            MessageMode::AddedOnly => format!(
+                "{}\n{}\nURL: {}\n",
+                texts.added,
+                self.vec_to_markdown(added),
+                self.urls.join(", ")
+            ),
+            MessageMode::Summary => format!(
+                "{}\nURL: {}\n",
+                texts.summary
+                    .replace("{added}", &added.len().to_string())
+                    .replace("{all_free}", &all_free.len().to_string())
+                    .replace("{removed}", &removed.len().to_string()),
+                self.urls.join(", ")
+            )
+        }
+    }
+
+    fn get(&self, uri: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(uri).headers(self.headers.clone());
+        match &self.auth {
+            Some(HttpAuth::Bearer{token}) => request.bearer_auth(token),
+            Some(HttpAuth::Basic{user, password}) => request.basic_auth(user, Some(password)),
+            None => request
+        }
+    }
+
+    /// Posts the login form, whose session cookie is kept by the client.
+    async fn log_in(&self, login: &LoginSettings) -> Result<(), AppError> {
+        info!("Logging in at {}", login.login_url);
+        let resp = self.client.post(&login.login_url)
+            .headers(self.headers.clone())
+            .form(&[("username", login.username.as_str()), ("password", login.password.as_str())])
+            .send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(AppError::Poll(format!("Login at {} failed with {}", login.login_url, status_error(status, &resp.text().await?))));
+        }
+        self.logged_in.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Fetches and parses a JSON document, failing with the status and body on HTTP errors.
+    ///
+    /// Servers under load sometimes send truncated or HTML responses, so the
+    /// document is fetched again up to `parse_retries` times if it is no valid JSON.
+    /// With a login, the session is established first and renewed once if the
+    /// server responds with 401.
+    async fn get_json(&self, uri: &str) -> Result<JsonValue, AppError> {
+        match self.get_json_if_modified(uri, &Validators::default()).await? {
+            Some((obj, _)) => Ok(obj),
+            None => Err(AppError::Poll(format!("HTTP 304 from {} to an unconditional request", uri)))
+        }
+    }
+
+    /// Like `get_json`, but returns `None` if the server answers with 304 Not Modified
+    /// to the `validators` of the previous response.
+    async fn get_json_if_modified(&self, uri: &str, validators: &Validators) -> Result<Option<(JsonValue, Validators)>, AppError> {
+        let mut attempt = 0;
+        let mut renewed = false;
+        loop {
+            if let Some(login) = &self.login {
+                if !self.logged_in.load(Ordering::Relaxed) {
+                    self.log_in(login).await?;
+                }
+            }
+            let mut request = self.get(uri);
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag.as_str());
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+            }
+            let resp = request.send().await?;
+            let status = resp.status();
+            if status == StatusCode::UNAUTHORIZED && self.login.is_some() && !renewed {
+                warn!("Session expired at {}. Logging in again.", uri);
+                self.logged_in.store(false, Ordering::Relaxed);
+                renewed = true;
+                continue;
+            }
+            if status == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            let new_validators = Validators::from(resp.headers());
+            let json_str = resp.text().await?;
+            if !status.is_success() {
+                return Err(AppError::Poll(format!("Request to {} failed with {}", uri, status_error(status, &json_str))));
+            }
+            match json::parse(&json_str) {
+                Ok(obj) => return Ok(Some((obj, new_validators))),
+                Err(error) if attempt < self.parse_retries => {
+                    attempt += 1;
+                    warn!("Invalid JSON from {} ({}). Retrying ({}/{}).", uri, error, attempt, self.parse_retries);
+                    async_std::task::sleep(PARSE_RETRY_DELAY).await;
+                },
+                Err(error) => return Err(error.into())
+            }
+        }
+    }
+
+    /// Fetches the overview unless it is unchanged since the cached one, see `get_json_if_modified`.
+    async fn get_overview_json(&self, url: &str) -> Result<Option<(JsonValue, Validators)>, AppError> {
+        let uri = format!("{}{}/Calendars/WithDetails", url, self.api_base_path);
+        let validators = match self.overview_cache.get(url) {
+            Some(cached) => cached.validators.clone(),
+            None => Validators::default()
+        };
+        self.get_json_if_modified(&uri, &validators).await
+    }
+
+    /// Merges the calendars of all servers.
+    async fn get_overview(&mut self) -> Result<HashMap<CalendarKey, Detail>, AppError> {
+        let mut details: HashMap<CalendarKey, Detail> = HashMap::new();
+        for url in self.urls.clone() {
+            for detail in self.get_server_overview(&url).await? {
+                details.insert(detail.key(), detail);
+            }
+        }
+        Ok(details)
     }
 
-    async fn get_overview(&self) -> Result<HashMap<u32, Detail>, Box<dyn Error>> {
-        let overview = self.get_overview_json().await?;
-        let mut details: HashMap<u32, Detail> = HashMap::new();
-        for detail_json in overview["Data"].members() {
-            let detail = Detail::from_json(&detail_json)?;
-            details.insert(detail.id, detail);
+    async fn get_server_overview(&mut self, url: &str) -> Result<Vec<Detail>, AppError> {
+        let (overview, validators) = match self.get_overview_json(url).await? {
+            Some(fetched) => fetched,
+            None => match self.overview_cache.get(url) {
+                Some(cached) => {
+                    info!("Calendar overview of {} is unchanged.", url);
+                    return Ok(cached.details.clone());
+                },
+                None => return Err(AppError::Poll(format!("HTTP 304 from {} without a cached calendar overview", url)))
+            }
+        };
+        let mut details: Vec<Detail> = Vec::new();
+        for detail_json in json_helper::response_array(&overview, "Data", "Calendar overview")?.members() {
+            details.push(Detail::from_json(url, detail_json)?);
+        }
+        if validators.is_empty() {
+            self.overview_cache.remove(url);
+        } else {
+            self.overview_cache.insert(String::from(url), CachedOverview{validators, details: details.clone()});
         }
         Ok(details)
     }
 
-    async fn first_free_slot_json(&self, id: u32) -> Result<JsonValue, Box<dyn Error>> {
-        let uri = format!("{}/rest-v2/api/Calendars/{}/FirstFreeSlot", self.url, id);
-        let resp = self.client.get(&uri).send().await?;
-        let json_str = resp.text().await?;
-        let obj = json::parse(&json_str)?;
-        Ok(obj)
+    async fn first_free_slot_json(&self, detail: &Detail) -> Result<JsonValue, AppError> {
+        let uri = format!("{}{}/Calendars/{}/FirstFreeSlot", detail.url, self.api_base_path, detail.id);
+        self.get_json(&uri).await
     }
 
-    async fn has_free_slots(&self, id: u32) -> Result<bool, Box<dyn Error>> {
-        let first_free_slot = self.first_free_slot_json(id).await?;
+    async fn has_free_slots(&self, detail: &Detail) -> Result<bool, AppError> {
+        let first_free_slot = self.first_free_slot_json(detail).await?;
         let has_free: bool = !first_free_slot["Data"].is_null();
         Ok(has_free)
     }
 
-    async fn extract_free_slots(&self, details: &HashMap<u32, Detail>) -> Result<HashMap<u32, Detail>, Box<dyn Error>> {
-        let mut free_slots: HashMap<u32, Detail> = HashMap::new();
-        for (id, detail) in details {
-            if self.has_free_slots(*id).await? {
-                free_slots.insert(*id, detail.clone());
+    async fn extract_free_slots(&self, details: &HashMap<CalendarKey, Detail>) -> Result<HashMap<CalendarKey, Detail>, AppError> {
+        let mut free_slots: HashMap<CalendarKey, Detail> = HashMap::new();
+        for (index, (key, detail)) in details.iter().enumerate() {
+            if index > 0 && !self.inter_request_delay.is_zero() {
+                async_std::task::sleep(self.inter_request_delay).await;
+            }
+            if self.has_free_slots(detail).await? {
+                free_slots.insert(key.clone(), detail.clone());
             }
         }
         Ok(free_slots)
     }
 
-    fn extract_added_slots(&self, free_slots: &HashMap<u32, Detail>) -> Vec<Detail> {
+    fn extract_added_slots(&self, free_slots: &HashMap<CalendarKey, Detail>) -> Vec<Detail> {
         let mut added: Vec<Detail> = Vec::new();
-        for (id, detail) in free_slots {
-            if !self.free_ids.contains(id) {
+        for (key, detail) in free_slots {
+            if !self.free_ids.contains(key) {
                 added.push(detail.clone());
             }
         }
         added
     }
 
-    fn map_to_set(slots: &HashMap<u32, Detail>) -> HashSet<u32> {
-        let mut set: HashSet<u32> = HashSet::new();
-        for (id, _) in slots {
-            set.insert(*id);
+    fn map_to_set(slots: &HashMap<CalendarKey, Detail>) -> HashSet<CalendarKey> {
+        let mut set: HashSet<CalendarKey> = HashSet::new();
+        for key in slots.keys() {
+            set.insert(key.clone());
         }
         set
     }
 
-    fn map_to_vec(slots: &HashMap<u32, Detail>) -> Vec<Detail> {
+    fn map_to_vec(slots: &HashMap<CalendarKey, Detail>) -> Vec<Detail> {
         let mut vec: Vec<Detail> = Vec::new();
-        for (_, detail) in slots {
+        for detail in slots.values() {
             vec.push(detail.clone());
         }
         vec
     }
 
-    fn extract_removed_slots(&self, free_set: &HashSet<u32>) -> Vec<Detail> {
+    fn extract_removed_slots(&self, free_set: &HashSet<CalendarKey>) -> Vec<Detail> {
         let mut removed: Vec<Detail> = Vec::new();
         let diff: HashSet<_> = self.free_ids.difference(free_set).collect();
-        for (id, detail) in &self.details {
-            if diff.contains(id) {
+        for (key, detail) in &self.details {
+            if diff.contains(key) {
                 removed.push(detail.clone());
             }
         }
         removed
     }
 
-    fn has_changed(&self, free_set: &HashSet<u32>) -> bool {
+    fn has_changed(&self, free_set: &HashSet<CalendarKey>) -> bool {
         let diff: HashSet<_> = self.free_ids.symmetric_difference(free_set).collect();
         !diff.is_empty()
     }
 
-    fn vec_to_markdown(slots: &Vec<Detail>) -> String {
+    /// Adds the booking page of each calendar if a template is set.
+    fn to_calendars(&self, slots: &[Detail]) -> Vec<Calendar> {
+        slots.iter()
+            .map(|slot| Calendar{
+                id: slot.id,
+                name: slot.name.clone(),
+                booking_url: self.booking_url_template.as_ref().map(|template| template
+                    .replace("{url}", &slot.url)
+                    .replace("{id}", &slot.id.to_string()))
+            })
+            .collect()
+    }
+
+    /// Lists the calendars, linking each to its booking page if it has one.
+    fn vec_to_markdown(&self, calendars: &[Calendar]) -> String {
         let mut text = String::new();
-        for slot in slots {
-            text = format!("{} * {} -- ID: {}\n", text, slot.name, slot.id);
+        for calendar in calendars {
+            let name = match &calendar.booking_url {
+                Some(url) => format!("[{}]({})", calendar.name, url),
+                None => calendar.name.clone()
+            };
+            text = format!("{} * {} -- ID: {}\n", text, name, calendar.id);
         }
         text
     }
 }
 
 impl ServiceProvider for Booked4us {
-    fn poll_once(&mut self) -> Result<PollResult, Box<dyn Error>> {
+    fn poll_once(&mut self) -> Result<PollResult, AppError> {
         async_std::task::block_on(self.async_poll())
     }
+
+    /// Urgent messages start with the counts, for previews showing only the first line.
+    fn render(&self, report: &PollReport, severity: Severity) -> String {
+        let text = self.render_message(report);
+        // Templates and summaries already contain the counts where the user wants them
+        if severity != Severity::Urgent || self.message_template.is_some() || self.message_mode == MessageMode::Summary {
+            return text;
+        }
+        let headline = self.language.texts().headline
+            .replace("{added}", &report.added.len().to_string())
+            .replace("{all_free}", &report.all_free.len().to_string());
+        format!("{}\n{}", headline, text)
+    }
+
+    fn self_check(&mut self) -> Result<(), AppError> {
+        async_std::task::block_on(self.get_overview())?;
+        Ok(())
+    }
+
+    fn url(&self) -> Option<String> {
+        self.urls.first().cloned()
+    }
+}
+
+/// `ETag` and `Last-Modified` of a response, sent back to only download changed documents.
+#[derive(Debug, Clone, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>
+}
+
+impl Validators {
+    fn from(headers: &HeaderMap) -> Validators {
+        let header = |name| headers.get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(String::from);
+        Validators{
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED)
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+#[derive(Debug)]
+struct CachedOverview {
+    validators: Validators,
+    details: Vec<Detail>
 }
 
 #[derive(Debug)]
 struct Detail {
-    id: u32,
+    /// Server the calendar belongs to
+    url: String,
+    id: u64,
     name: String,
 }
 
 impl Detail {
-    fn from_json(json: &JsonValue) -> Result<Self, Box<dyn Error>> {
+    fn from_json(url: &str, json: &JsonValue) -> Result<Self, AppError> {
         let detail = Detail {
-            id: json_helper::obj_to_u32(&json["Id"])?,
+            url: String::from(url),
+            id: json_helper::obj_to_id(&json["Id"])?,
             name: json_helper::obj_to_str(&json["Name"])?,
         };
         Ok(detail)
     }
+
+    fn key(&self) -> CalendarKey {
+        (self.url.clone(), self.id)
+    }
 }
 
 impl Clone for Detail {
     fn clone(&self) -> Self {
         Detail {
+            url: self.url.clone(),
             id: self.id,
             name: self.name.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::{Mock, MockServer};
+    use json::object;
+
+    fn booked4us(server: &MockServer) -> Booked4us {
+        booked4us_with(server, object!{})
+    }
+
+    /// Creates a provider for the mock server with additional settings.
+    fn booked4us_with(server: &MockServer, mut settings: json::JsonValue) -> Booked4us {
+        settings["url"] = server.base_url().into();
+        Booked4us::from(&Booked4usSettings::load_from_json_object(&settings).unwrap(), &ClientPool::new(Duration::from_secs(10)))
+    }
+
+    /// Serves the calendars `all` of which the ones in `free` have a free slot.
+    fn mock_calendars<'a>(server: &'a MockServer, all: &[(u64, &str)], free: &[u64]) -> Vec<Mock<'a>> {
+        let mut overview = json::JsonValue::new_array();
+        for (id, name) in all {
+            overview.push(object!{"Id" => *id, "Name" => *name}).unwrap();
+        }
+        let body = object!{"Data" => overview}.dump();
+        let mut mocks = vec![server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails");
+            then.status(200).body(body);
+        })];
+        for (id, _) in all {
+            let slot = if free.contains(id) {
+                r#"{"Data": {"Start": "2021-05-01T08:00:00"}}"#
+            } else {
+                r#"{"Data": null}"#
+            };
+            mocks.push(server.mock(|when, then| {
+                when.path(format!("/rest-v2/api/Calendars/{}/FirstFreeSlot", id));
+                then.status(200).body(slot);
+            }));
+        }
+        mocks
+    }
+
+    fn clear(mocks: Vec<Mock>) {
+        for mut mock in mocks {
+            mock.delete();
+        }
+    }
+
+    #[test]
+    fn first_poll_with_free_slots_is_urgent() {
+        let server = MockServer::start();
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1]);
+        let mut provider = booked4us(&server);
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => {
+                let text = provider.render(&report, Severity::Urgent);
+                assert!(text.starts_with("1 neu / 1 insgesamt frei\nFrei gewordene Kategorien:\n"));
+                assert!(text.contains("BioNTech -- ID: 1"));
+                assert!(!text.contains("Moderna"));
+            },
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn disappearing_slot_is_reported_as_removed() {
+        let server = MockServer::start();
+        let mut provider = booked4us(&server);
+        let mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1, 2]);
+        provider.poll_once().unwrap();
+        clear(mocks);
+
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1]);
+        match provider.poll_once().unwrap() {
+            PollResult::Normal(report) => {
+                let removed: Vec<u64> = report.removed.iter().map(|calendar| calendar.id).collect();
+                assert_eq!(removed, vec![2]);
+                let text = provider.render(&report, Severity::Normal);
+                assert!(text.split("Nicht mehr frei:").nth(1).unwrap().contains("Moderna -- ID: 2"));
+            },
+            other => panic!("Expected normal result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn summary_is_written_in_the_configured_language() {
+        let server = MockServer::start();
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1, 2]);
+        let mut provider = booked4us_with(&server, object!{"message_mode" => "summary", "language" => "en"});
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert!(provider.render(&report, Severity::Urgent).starts_with("Newly free: 2, all free: 2, no longer free: 0\n")),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn unchanged_slots_return_none() {
+        let server = MockServer::start();
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[2]);
+        let mut provider = booked4us(&server);
+        provider.poll_once().unwrap();
+
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::None));
+    }
+
+    #[test]
+    fn newly_freed_slot_after_first_poll_is_urgent() {
+        let server = MockServer::start();
+        let mut provider = booked4us(&server);
+        let mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[]);
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::None));
+        clear(mocks);
+
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[2]);
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert!(provider.render(&report, Severity::Urgent).contains("Moderna -- ID: 2")),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn booking_url_template_links_calendars() {
+        let server = MockServer::start();
+        let _mocks = mock_calendars(&server, &[(7, "BioNTech")], &[7]);
+        let mut provider = booked4us_with(&server, object!{"booking_url_template" => "{url}/booking/{id}"});
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert!(provider.render(&report, Severity::Urgent).contains(&format!("[BioNTech]({}/booking/7) -- ID: 7", server.base_url()))),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn large_string_ids_are_accepted() {
+        let server = MockServer::start();
+        let _overview = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails");
+            then.status(200).body(r#"{"Data": [{"Id": "12345678901", "Name": "BioNTech"}]}"#);
+        });
+        let _slot = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/12345678901/FirstFreeSlot");
+            then.status(200).body(r#"{"Data": {"Start": "2021-05-01T08:00:00"}}"#);
+        });
+        let mut provider = booked4us(&server);
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert!(provider.render(&report, Severity::Urgent).contains("BioNTech -- ID: 12345678901")),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn overview_without_data_is_an_error() {
+        let server = MockServer::start();
+        let _overview = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails");
+            then.status(200).body(r#"{"Error": "Maintenance"}"#);
+        });
+        let mut provider = booked4us(&server);
+
+        match provider.poll_once() {
+            Err(AppError::Poll(msg)) => assert_eq!(msg, r#"Calendar overview has no Data array: {"Error":"Maintenance"}"#),
+            other => panic!("Expected poll error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn calendars_of_all_urls_are_merged() {
+        let north = MockServer::start();
+        let south = MockServer::start();
+        let _north_mocks = mock_calendars(&north, &[(1, "Nord")], &[1]);
+        let _south_mocks = mock_calendars(&south, &[(1, "Süd")], &[1]);
+        let settings = object!{
+            "url" => json::array![north.base_url(), south.base_url()],
+            "booking_url_template" => "{url}/booking/{id}"
+        };
+        let mut provider = Booked4us::from(&Booked4usSettings::load_from_json_object(&settings).unwrap(), &ClientPool::new(Duration::from_secs(10)));
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => {
+                let mut links: Vec<String> = report.added.iter().filter_map(|calendar| calendar.booking_url.clone()).collect();
+                links.sort();
+                let mut expected = vec![format!("{}/booking/1", north.base_url()), format!("{}/booking/1", south.base_url())];
+                expected.sort();
+                assert_eq!(links, expected);
+            },
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn expired_session_is_renewed() {
+        let server = MockServer::start();
+        let login = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/login").body("username=me&password=secret");
+            then.status(200).header("Set-Cookie", "session=abc; Path=/");
+        });
+        let rejected = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails");
+            then.status(401);
+        });
+        let mut provider = booked4us_with(&server, object!{
+            "login" => object!{"login_url" => server.url("/login"), "username" => "me", "password" => "secret"}
+        });
+        assert!(matches!(provider.poll_once(), Err(AppError::Poll(_))));
+        login.assert_hits(2);
+        rejected.assert_hits(2);
+        clear(vec![rejected]);
+
+        let overview = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails").cookie("session", "abc");
+            then.status(200).body(r#"{"Data": []}"#);
+        });
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::None));
+        overview.assert();
+        login.assert_hits(2);
+    }
+
+    #[test]
+    fn unchanged_overview_is_taken_from_the_cache() {
+        let server = MockServer::start();
+        let overview = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails");
+            then.status(200).header("ETag", "\"v1\"").body(r#"{"Data": [{"Id": 1, "Name": "BioNTech"}]}"#);
+        });
+        let _slot = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/1/FirstFreeSlot");
+            then.status(200).body(r#"{"Data": {"Start": "2021-05-01T08:00:00"}}"#);
+        });
+        let mut provider = booked4us(&server);
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::Urgent(_)));
+        clear(vec![overview]);
+
+        let not_modified = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails").header("If-None-Match", "\"v1\"");
+            then.status(304);
+        });
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::None));
+        not_modified.assert();
+        assert_eq!(provider.details.len(), 1);
+    }
+
+    #[test]
+    fn invalid_json_is_fetched_again() {
+        let server = MockServer::start();
+        let overview = server.mock(|when, then| {
+            when.path("/rest-v2/api/Calendars/WithDetails");
+            then.status(200).body("<html>Busy</html>");
+        });
+        let mut provider = booked4us_with(&server, object!{"parse_retries" => 1});
+
+        assert!(matches!(provider.poll_once(), Err(AppError::Json(_))));
+        overview.assert_hits(2);
+    }
+
+    #[test]
+    fn fewer_added_slots_than_min_added_are_normal() {
+        let server = MockServer::start();
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1, 2]);
+        let mut provider = booked4us_with(&server, object!{"min_added" => 3});
+
+        match provider.poll_once().unwrap() {
+            PollResult::Normal(report) => assert!(provider.render(&report, Severity::Normal).contains("Moderna -- ID: 2")),
+            other => panic!("Expected normal result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn important_calendar_is_urgent_despite_min_added() {
+        let server = MockServer::start();
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1, 2]);
+        let mut provider = booked4us_with(&server, object!{"min_added" => 3, "important_ids" => json::array![2]});
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert!(report.important),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn removed_slot_is_urgent_if_announced() {
+        let server = MockServer::start();
+        let mut provider = booked4us_with(&server, object!{"message_mode" => "added_only", "notify_on_removed" => true});
+        let mocks = mock_calendars(&server, &[(1, "BioNTech")], &[1]);
+        provider.poll_once().unwrap();
+        clear(mocks);
+
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech")], &[]);
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert!(provider.render(&report, Severity::Urgent).contains("Nicht mehr frei:\n * BioNTech -- ID: 1")),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn only_watched_calendars_are_polled() {
+        let server = MockServer::start();
+        let mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1, 2]);
+        let mut provider = booked4us_with(&server, object!{"watch_ids" => json::array![2]});
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert_eq!(report.added.iter().map(|calendar| calendar.id).collect::<Vec<u64>>(), vec![2]),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+        mocks[1].assert_hits(0);
+    }
+
+    #[test]
+    fn silent_first_poll_only_sets_the_baseline() {
+        let server = MockServer::start();
+        let mut provider = booked4us_with(&server, object!{"silent_first_poll" => true});
+        let mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1]);
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::None));
+        clear(mocks);
+
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech"), (2, "Moderna")], &[1, 2]);
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => assert_eq!(report.added.iter().map(|calendar| calendar.id).collect::<Vec<u64>>(), vec![2]),
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn slot_freed_again_within_cooldown_is_normal() {
+        let server = MockServer::start();
+        let mut provider = booked4us_with(&server, object!{"urgent_cooldown_secs" => 3600});
+        let mocks = mock_calendars(&server, &[(1, "BioNTech")], &[1]);
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::Urgent(_)));
+        clear(mocks);
+        let mocks = mock_calendars(&server, &[(1, "BioNTech")], &[]);
+        provider.poll_once().unwrap();
+        clear(mocks);
+        let _mocks = mock_calendars(&server, &[(1, "BioNTech")], &[1]);
+
+        match provider.poll_once().unwrap() {
+            PollResult::Normal(report) => assert!(provider.render(&report, Severity::Normal).contains("BioNTech -- ID: 1")),
+            other => panic!("Expected normal result, got {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,270 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::AppError;
+use crate::service::{ServiceProvider, PollResult, PollReport, Calendar};
+use crate::notification::Severity;
+use crate::config::DoctolibSettings;
+use crate::i18n::Language;
+use crate::http_client::{ClientPool, status_error};
+use crate::json_helper;
+use json::JsonValue;
+use chrono::{Datelike, Local, NaiveDate};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use std::collections::HashMap;
+use log::info;
+
+/// Polls the `availabilities.json` API of Doctolib.
+///
+/// Doctolib lists the slots of each day instead of free flags per calendar,
+/// so every day with at least one slot counts as a free calendar. Its ID is
+/// the date, e.g. `20210501`.
+#[derive(Debug)]
+pub struct Doctolib {
+    url: String,
+    booking_url: String,
+    client: reqwest::Client,
+    headers: HeaderMap,
+    /// IDs joined by `-`, as expected by the API
+    visit_motive_ids: String,
+    agenda_ids: String,
+    practice_ids: String,
+    start_date: Option<NaiveDate>,
+    days: u32,
+    language: Language,
+    free_days: HashMap<u64, Day>
+}
+
+impl Doctolib {
+    pub fn from(settings: &DoctolibSettings, clients: &ClientPool) -> Doctolib {
+        let mut headers = HeaderMap::new();
+        if let Some(user_agent) = &settings.user_agent {
+            headers.insert(USER_AGENT, HeaderValue::from_str(user_agent).expect("User agent is validated when loading the config"));
+        }
+        Doctolib{
+            url: settings.url.clone(),
+            booking_url: settings.booking_url.clone(),
+            client: clients.get(&settings.url, &settings.proxy, &settings.tls, &settings.network),
+            headers,
+            visit_motive_ids: Self::join_ids(&settings.visit_motive_ids),
+            agenda_ids: Self::join_ids(&settings.agenda_ids),
+            practice_ids: Self::join_ids(&settings.practice_ids),
+            start_date: settings.start_date,
+            days: settings.days,
+            language: settings.language,
+            free_days: HashMap::new()
+        }
+    }
+
+    fn join_ids(ids: &[u64]) -> String {
+        ids.iter().map(|id| id.to_string()).collect::<Vec<String>>().join("-")
+    }
+
+    async fn async_poll(&mut self) -> Result<PollResult, AppError> {
+        let free_days = self.get_free_days().await?;
+        info!("Free Days: {:?}", free_days);
+        let added: Vec<Day> = free_days.iter()
+            .filter(|(id, _)| !self.free_days.contains_key(id))
+            .map(|(_, day)| day.clone())
+            .collect();
+        let removed: Vec<Day> = self.free_days.iter()
+            .filter(|(id, _)| !free_days.contains_key(id))
+            .map(|(_, day)| day.clone())
+            .collect();
+        if added.is_empty() && removed.is_empty() {
+            return Ok(PollResult::None);
+        }
+        info!("Free Days have changed.");
+        let all_free: Vec<Day> = free_days.values().cloned().collect();
+        let report = PollReport{
+            added: self.to_calendars(&added),
+            all_free: self.to_calendars(&all_free),
+            removed: self.to_calendars(&removed),
+            important: false
+        };
+        self.free_days = free_days;
+        if added.is_empty() {
+            Ok(PollResult::Normal(report))
+        } else {
+            Ok(PollResult::Urgent(report))
+        }
+    }
+
+    async fn get_availabilities_json(&self) -> Result<JsonValue, AppError> {
+        let uri = format!("{}/availabilities.json", self.url);
+        let start_date = self.start_date.unwrap_or_else(|| Local::now().date_naive());
+        let mut query = vec![
+            ("start_date", start_date.format("%Y-%m-%d").to_string()),
+            ("visit_motive_ids", self.visit_motive_ids.clone()),
+            ("agenda_ids", self.agenda_ids.clone()),
+            ("insurance_sector", String::from("public")),
+            ("limit", self.days.to_string())
+        ];
+        if !self.practice_ids.is_empty() {
+            query.push(("practice_ids", self.practice_ids.clone()));
+        }
+        let resp = self.client.get(&uri).headers(self.headers.clone()).query(&query).send().await?;
+        let status = resp.status();
+        let json_str = resp.text().await?;
+        if !status.is_success() {
+            return Err(AppError::Poll(format!("Request to {} failed with {}", uri, status_error(status, &json_str))));
+        }
+        Ok(json::parse(&json_str)?)
+    }
+
+    async fn get_free_days(&self) -> Result<HashMap<u64, Day>, AppError> {
+        let availabilities = self.get_availabilities_json().await?;
+        let mut free_days: HashMap<u64, Day> = HashMap::new();
+        for day_json in json_helper::response_array(&availabilities, "availabilities", "Availability response")?.members() {
+            let day = Day::from_json(day_json)?;
+            if day.slots > 0 {
+                free_days.insert(day.id(), day);
+            }
+        }
+        Ok(free_days)
+    }
+
+    /// Sorts the days by date and names them with their number of slots.
+    fn to_calendars(&self, days: &[Day]) -> Vec<Calendar> {
+        let mut days = days.to_vec();
+        days.sort_by_key(|day| day.date);
+        days.iter()
+            .map(|day| Calendar{
+                id: day.id(),
+                name: format!("{}: {}", day.date.format("%Y-%m-%d"), self.language.texts().slots.replace("{count}", &day.slots.to_string())),
+                booking_url: Some(self.booking_url.clone())
+            })
+            .collect()
+    }
+
+    fn vec_to_markdown(calendars: &[Calendar]) -> String {
+        let mut text = String::new();
+        for calendar in calendars {
+            text = format!("{} * {}\n", text, calendar.name);
+        }
+        text
+    }
+}
+
+impl ServiceProvider for Doctolib {
+    fn poll_once(&mut self) -> Result<PollResult, AppError> {
+        async_std::task::block_on(self.async_poll())
+    }
+
+    fn render(&self, report: &PollReport, severity: Severity) -> String {
+        let texts = self.language.texts();
+        let headline = match severity {
+            Severity::Urgent => format!("{}\n", texts.headline
+                .replace("{added}", &report.added.len().to_string())
+                .replace("{all_free}", &report.all_free.len().to_string())),
+            Severity::Normal => String::new()
+        };
+        format!(
+            "{}{}\n{}\n{}\n{}\n{}\n{}\nURL: {}\n",
+            headline,
+            texts.added_days,
+            Self::vec_to_markdown(&report.added),
+            texts.all_free_days,
+            Self::vec_to_markdown(&report.all_free),
+            texts.removed,
+            Self::vec_to_markdown(&report.removed),
+            self.booking_url
+        )
+    }
+
+    fn self_check(&mut self) -> Result<(), AppError> {
+        async_std::task::block_on(self.get_free_days())?;
+        Ok(())
+    }
+
+    fn url(&self) -> Option<String> {
+        Some(self.booking_url.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Day {
+    date: NaiveDate,
+    slots: usize
+}
+
+impl Day {
+    fn from_json(json: &JsonValue) -> Result<Self, AppError> {
+        let date = json_helper::obj_to_str(&json["date"])?;
+        let date = match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) => return Err(AppError::Poll(format!("Invalid date in availabilities: {}", date)))
+        };
+        Ok(Day{
+            date,
+            slots: json["slots"].len()
+        })
+    }
+
+    fn id(&self) -> u64 {
+        self.date.year() as u64 * 10000 + self.date.month() as u64 * 100 + self.date.day() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use json::object;
+    use std::time::Duration;
+
+    fn doctolib(server: &MockServer) -> Doctolib {
+        let settings = object!{
+            "url" => server.base_url(),
+            "booking_url" => "https://www.doctolib.de/praxis/berlin/impfzentrum",
+            "visit_motive_ids" => json::array![1, 2],
+            "agenda_ids" => json::array![3],
+            "start_date" => "2021-05-01",
+            "language" => "en"
+        };
+        Doctolib::from(&DoctolibSettings::load_from_json_object(&settings).unwrap(), &ClientPool::new(Duration::from_secs(10)))
+    }
+
+    #[test]
+    fn days_with_slots_are_free() {
+        let server = MockServer::start();
+        let availabilities = server.mock(|when, then| {
+            when.path("/availabilities.json")
+                .query_param("start_date", "2021-05-01")
+                .query_param("visit_motive_ids", "1-2")
+                .query_param("agenda_ids", "3");
+            then.status(200).body(r#"{"availabilities": [
+                {"date": "2021-05-01", "slots": []},
+                {"date": "2021-05-02", "slots": ["2021-05-02T08:00:00", "2021-05-02T08:10:00"]}
+            ], "total": 2}"#);
+        });
+        let mut provider = doctolib(&server);
+
+        match provider.poll_once().unwrap() {
+            PollResult::Urgent(report) => {
+                assert_eq!(report.added.iter().map(|calendar| calendar.id).collect::<Vec<u64>>(), vec![20210502]);
+                assert!(provider.render(&report, Severity::Urgent).starts_with("1 new / 1 total free\nNewly free days:\n * 2021-05-02: 2 free slots\n"));
+            },
+            other => panic!("Expected urgent result, got {:?}", other)
+        }
+        assert!(matches!(provider.poll_once().unwrap(), PollResult::None));
+        availabilities.assert_hits(2);
+    }
+
+    #[test]
+    fn response_without_availabilities_is_an_error() {
+        let server = MockServer::start();
+        let _availabilities = server.mock(|when, then| {
+            when.path("/availabilities.json");
+            then.status(200).body(r#"{"error": "Maintenance"}"#);
+        });
+        let mut provider = doctolib(&server);
+
+        assert!(matches!(provider.poll_once(), Err(AppError::Poll(_))));
+    }
+}
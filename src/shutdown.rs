@@ -0,0 +1,78 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Signals the owner of a worker thread that the thread has finished.
+///
+/// The signal is sent on drop, so it also fires when the thread panics.
+pub struct DoneSignal {
+    done_tx: mpsc::Sender<()>
+}
+
+impl DoneSignal {
+    pub fn new(done_tx: mpsc::Sender<()>) -> DoneSignal {
+        DoneSignal{
+            done_tx
+        }
+    }
+}
+
+impl Drop for DoneSignal {
+    fn drop(&mut self) {
+        let _ = self.done_tx.send(());
+    }
+}
+
+/// Records when a shutdown of a group of threads was requested.
+#[derive(Debug, Clone)]
+pub struct ShutdownRequest {
+    requested: Arc<Mutex<Option<Instant>>>
+}
+
+impl ShutdownRequest {
+    pub fn new() -> ShutdownRequest {
+        ShutdownRequest{
+            requested: Arc::new(Mutex::new(None))
+        }
+    }
+
+    pub fn request(&self) {
+        let mut requested = self.requested.lock().unwrap();
+        if requested.is_none() {
+            *requested = Some(Instant::now());
+        }
+    }
+
+    fn requested_at(&self) -> Option<Instant> {
+        *self.requested.lock().unwrap()
+    }
+}
+
+/// Waits until `count` threads have signalled completion.
+///
+/// Once a shutdown has been requested, waiting is limited to `timeout`.
+/// Returns `false` on timeout.
+pub fn wait_for(done_rx: &mpsc::Receiver<()>, count: usize, shutdown: &ShutdownRequest, timeout: Duration) -> bool {
+    let mut finished = 0;
+    while finished < count {
+        match done_rx.recv_timeout(Duration::from_secs(1)) {
+            Ok(_) => finished += 1,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if let Some(requested) = shutdown.requested_at() {
+                    if requested.elapsed() >= timeout {
+                        return false;
+                    }
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => break
+        }
+    }
+    true
+}
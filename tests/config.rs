@@ -0,0 +1,263 @@
+/*
+ * SPDX-License-Identifier: MPL-2.0
+ *   Copyright (c) 2021 Philipp Le <philipp@philipple.de>.
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use covid_vacc_poll::config::{AppriseTarget, Config, HttpAuth, MessageMode, NotificationProviderSettings, ServiceProviderSettings};
+use covid_vacc_poll::notification::{MessageFormat, Severity};
+
+/// Writes `content` to a config file unique to the test.
+fn write_config(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("covid-vacc-poll-{}-{}.json", process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn read_config(name: &str, content: &str) -> Result<Config, String> {
+    let path = write_config(name, content);
+    let config = Config::read_from_file(path.to_str().unwrap()).map_err(|error| error.to_string());
+    fs::remove_file(path).unwrap();
+    config
+}
+
+const REPRESENTATIVE: &str = r#"{
+    "admin_notifications": ["gotify"],
+    "default_sleep": 90,
+    "shutdown_timeout_secs": 10,
+    "services": [
+        {
+            "provider": "booked4us",
+            "title": "Impfzentrum",
+            "notifications": ["gotify", "apprise"],
+            "sleep": 120,
+            "jitter_secs": 15,
+            "settings": {
+                "url": "https://booked4us.example.com",
+                "api_base_path": "/api/",
+                "auth": {"type": "basic", "user": "me", "password": "secret"},
+                "message_mode": "summary",
+                "min_added": 2
+            }
+        },
+        {
+            "provider": "booked4us",
+            "title": "Hausarzt",
+            "enabled": false,
+            "notifications": [],
+            "settings": {"url": "https://doc.example.com"}
+        }
+    ],
+    "notifications": {
+        "gotify": {
+            "provider": "gotify",
+            "max_retries": 5,
+            "min_severity": "urgent",
+            "settings": {"url": "https://gotify.example.com", "application_token": "token", "format": "plaintext"}
+        },
+        "apprise": {
+            "provider": "apprise",
+            "force_severity": "normal",
+            "settings": {"server_url": "http://apprise:8000/", "urls": ["tgram://bot/chat"]}
+        }
+    }
+}"#;
+
+#[test]
+fn representative_config_is_parsed_completely() {
+    let config = read_config("representative", REPRESENTATIVE).unwrap();
+
+    assert_eq!(config.admin_notifications.errors, vec!["gotify"]);
+    assert_eq!(config.admin_notifications.lifecycle, vec!["gotify"]);
+    assert_eq!(config.shutdown_timeout_secs, 10);
+    assert_eq!(config.max_runtime_secs, None);
+    assert!(config.log_file.is_none());
+
+    assert_eq!(config.services.len(), 2);
+    let srv = &config.services[0];
+    assert_eq!(srv.title, "Impfzentrum");
+    assert_eq!(srv.notifications, vec!["gotify", "apprise"]);
+    assert_eq!(srv.sleep, 120);
+    assert_eq!(srv.jitter_secs, 15);
+    assert!(srv.enabled);
+    assert!(srv.cron.is_none());
+    match &srv.provider {
+        ServiceProviderSettings::Booked4us(settings) => {
+            assert_eq!(settings.urls, vec!["https://booked4us.example.com"]);
+            assert_eq!(settings.api_base_path, "/api");
+            assert_eq!(settings.auth, Some(HttpAuth::Basic{user: String::from("me"), password: String::from("secret")}));
+            assert_eq!(settings.message_mode, MessageMode::Summary);
+            assert_eq!(settings.min_added, 2);
+        },
+        other => panic!("Expected Booked4us settings, got {:?}", other)
+    }
+    let disabled = &config.services[1];
+    assert!(!disabled.enabled);
+    assert_eq!(disabled.sleep, 90);
+
+    assert_eq!(config.notifications.len(), 2);
+    let gotify = &config.notifications["gotify"];
+    assert_eq!(gotify.max_retries, 5);
+    assert_eq!(gotify.retry_delay_secs, 2);
+    assert_eq!(gotify.min_severity, Severity::Urgent);
+    match &gotify.provider {
+        NotificationProviderSettings::Gotify(settings) => {
+            assert_eq!(settings.url, "https://gotify.example.com");
+            assert_eq!(settings.application_token, "token");
+            assert_eq!(settings.format, MessageFormat::Plaintext);
+        },
+        other => panic!("Expected Gotify settings, got {:?}", other)
+    }
+    let apprise = &config.notifications["apprise"];
+    assert_eq!(apprise.force_severity, Some(Severity::Normal));
+    match &apprise.provider {
+        NotificationProviderSettings::Apprise(settings) => {
+            assert_eq!(settings.server_url, "http://apprise:8000");
+            assert_eq!(settings.target, AppriseTarget::Urls(vec![String::from("tgram://bot/chat")]));
+        },
+        other => panic!("Expected Apprise settings, got {:?}", other)
+    }
+}
+
+#[test]
+fn unknown_service_provider_is_rejected() {
+    let error = read_config("unknown-service", r#"{
+        "admin_notifications": [],
+        "services": [{"provider": "impfterminservice", "title": "x", "notifications": [], "sleep": 60, "settings": {}}],
+        "notifications": {}
+    }"#).unwrap_err();
+    assert_eq!(error, "Config parsing error: services[0].provider: unknown provider \"impfterminservice\"");
+}
+
+#[test]
+fn jitter_as_long_as_sleep_is_rejected() {
+    let error = read_config("jitter", r#"{
+        "admin_notifications": [],
+        "services": [{"provider": "booked4us", "title": "x", "notifications": [], "sleep": 30, "jitter_secs": 30, "settings": {"url": "https://a.example.com"}}],
+        "notifications": {}
+    }"#).unwrap_err();
+    assert_eq!(error, "Config parsing error: services[0].jitter_secs: must be less than sleep");
+}
+
+#[test]
+fn short_sleep_is_a_warning() {
+    let config = read_config("short-sleep", r#"{
+        "admin_notifications": [],
+        "services": [{"provider": "booked4us", "title": "x", "notifications": [], "sleep": 10, "settings": {"url": "https://a.example.com"}}],
+        "notifications": {}
+    }"#).unwrap();
+    assert!(config.warnings().contains(&String::from("Service x polls every 10 s. Intervals below 30 s may get you blocked.")));
+}
+
+#[test]
+fn unknown_notification_provider_is_rejected() {
+    let error = read_config("unknown-notification", r#"{
+        "admin_notifications": [],
+        "services": [],
+        "notifications": {"pager": {"provider": "pager", "settings": {}}}
+    }"#).unwrap_err();
+    assert_eq!(error, "Config parsing error: notifications.pager.provider: unknown provider \"pager\"");
+}
+
+#[test]
+fn undefined_notification_reference_is_rejected() {
+    let error = read_config("undefined-reference", r#"{
+        "admin_notifications": ["missing"],
+        "services": [],
+        "notifications": {}
+    }"#).unwrap_err();
+    assert_eq!(error, "Config parsing error: Notification missing is referenced but not defined");
+}
+
+#[test]
+fn missing_services_section_is_rejected() {
+    let error = read_config("missing-services", r#"{
+        "admin_notifications": [],
+        "notifications": {}
+    }"#).unwrap_err();
+    assert_eq!(error, "Config parsing error: services: expected an array, found null");
+}
+
+/// Creates an empty config directory unique to the test.
+fn config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("covid-vacc-poll-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn config_dir_is_merged() {
+    let dir = config_dir("merged");
+    fs::write(dir.join("00-global.json"), r#"{
+        "admin_notifications": ["gotify"],
+        "shutdown_timeout_secs": 10,
+        "notifications": {
+            "gotify": {"provider": "gotify", "settings": {"url": "https://gotify.example.com", "application_token": "token"}}
+        }
+    }"#).unwrap();
+    fs::write(dir.join("10-family.json"), r#"{
+        "services": [
+            {"provider": "booked4us", "title": "Family", "notifications": ["gotify", "file"], "sleep": 60, "settings": {"url": "https://a.example.com"}}
+        ],
+        "notifications": {
+            "file": {"provider": "file", "settings": {"path": "/tmp/appointments.txt"}}
+        }
+    }"#).unwrap();
+    fs::write(dir.join("README.md"), "Not a config").unwrap();
+
+    let config = Config::read_from_dir(dir.to_str().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+    let config = config.unwrap();
+
+    assert_eq!(config.shutdown_timeout_secs, 10);
+    assert_eq!(config.admin_notifications.errors, vec!["gotify"]);
+    assert_eq!(config.services.len(), 1);
+    assert_eq!(config.services[0].title, "Family");
+    assert_eq!(config.notifications.len(), 2);
+}
+
+#[test]
+fn config_dir_rejects_duplicate_notifications() {
+    let dir = config_dir("duplicate");
+    let content = r#"{
+        "notifications": {"file": {"provider": "file", "settings": {"path": "/tmp/appointments.txt"}}}
+    }"#;
+    fs::write(dir.join("a.json"), content).unwrap();
+    fs::write(dir.join("b.json"), content).unwrap();
+
+    let error = Config::read_from_dir(dir.to_str().unwrap()).unwrap_err().to_string();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(error, format!("Config parsing error: {}: Notification file is defined more than once", dir.join("b.json").display()));
+}
+
+#[test]
+fn check_lists_all_dangling_references() {
+    let path = write_config("dangling", r#"{
+        "admin_notifications": ["missing"],
+        "services": [{"provider": "booked4us", "title": "A", "notifications": ["gone"], "sleep": 60, "settings": {"url": "https://a.example.com"}}],
+        "notifications": {}
+    }"#);
+    let config = Config::read_unvalidated_from_file(path.to_str().unwrap());
+    fs::remove_file(path).unwrap();
+
+    let validation = config.unwrap().check();
+    assert!(!validation.is_ok());
+    let references: Vec<(&str, &str)> = validation.dangling_references.iter()
+        .map(|reference| (reference.notification.as_str(), reference.referenced_by.as_str()))
+        .collect();
+    assert_eq!(references, vec![
+        ("missing", "admin_notifications.errors"),
+        ("missing", "admin_notifications.lifecycle"),
+        ("gone", "services.A")
+    ]);
+    assert_eq!(validation.errors(), vec![
+        "Notification missing is referenced but not defined",
+        "Notification gone is referenced but not defined"
+    ]);
+}